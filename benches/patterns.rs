@@ -0,0 +1,70 @@
+// Benchmarks for common kinds of pattern. BENCHMARKS.md records the results they are expected
+// to stay close to. Run with: cargo bench
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use mygrep::{RegexBuilder, RegexEngine};
+
+// About 1 MB of words with a number after every seventh one
+fn prose() -> String {
+    let words = ["lorem", "ipsum", "dolor", "sit", "amet", "consectetur", "adipiscing"];
+    let mut text = String::with_capacity(1 << 20);
+    let mut i = 0;
+    while text.len() < 1 << 20 {
+        text.push_str(words[i % words.len()]);
+        text.push(if i % 7 == 6 { '\n' } else { ' ' });
+        if i % 7 == 6 {
+            text.push_str(&(i * 37).to_string());
+            text.push(' ');
+        }
+        i += 1;
+    }
+    text
+}
+
+fn literal(c: &mut Criterion) {
+    let text = prose() + "needle";
+    let engine = RegexEngine::new("needle").unwrap();
+    c.bench_function("literal in 1 MB", |b| b.iter(|| engine.find(black_box(&text))));
+}
+
+fn digits(c: &mut Criterion) {
+    let text = "31415926535 8979323846 2643383279 5028841971 ".repeat(100);
+    let engine = RegexEngine::new(r"\d+").unwrap();
+    c.bench_function(r"\d+ over numbers", |b| b.iter(|| engine.find_iter(black_box(&text)).count()));
+}
+
+fn letters(c: &mut Criterion) {
+    let text = prose();
+    let text = &text[..64 * 1024];
+    let engine = RegexEngine::new("[a-zA-Z]+").unwrap();
+    c.bench_function("[a-zA-Z]+ over 64 KB of words", |b| b.iter(|| engine.find_iter(black_box(text)).count()));
+}
+
+fn alternation(c: &mut Criterion) {
+    let text = prose();
+    let text = &text[..64 * 1024];
+    let engine = RegexEngine::new("cat|dog|bird|fish|amet").unwrap();
+    c.bench_function("5-arm alternation over 64 KB", |b| b.iter(|| engine.find_iter(black_box(text)).count()));
+}
+
+fn backreference(c: &mut Criterion) {
+    let text = "the quick brown fox jumps over the lazy dog dog again ".repeat(20);
+    let engine = RegexEngine::new(r"\b(\w+) \1\b").unwrap();
+    c.bench_function("repeated word backreference", |b| b.iter(|| engine.find_iter(black_box(&text)).count()));
+}
+
+// (a+)+b never matches a run of a's, and without memoization every way of splitting the run is
+// tried, which doubles the work with each extra 'a'
+fn catastrophic(c: &mut Criterion) {
+    let text = "a".repeat(16);
+    let memoized = RegexEngine::new("(a+)+b").unwrap();
+    let plain = RegexBuilder::new("(a+)+b").memoize(false).backtrack_limit(usize::MAX).build().unwrap();
+    c.bench_function("(a+)+b on 16 a's, memoized", |b| b.iter(|| memoized.find(black_box(&text))));
+    c.bench_function("(a+)+b on 16 a's, not memoized", |b| b.iter(|| plain.find(black_box(&text))));
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = literal, digits, letters, alternation, backreference, catastrophic
+}
+criterion_main!(benches);
@@ -0,0 +1,30 @@
+// Times patterns that backtrack exponentially, with and without memoization.
+// Run with: cargo run --release --example backtracking
+use std::time::Instant;
+
+use mygrep::RegexBuilder;
+
+fn main() {
+    for pattern in ["(a+)+b", "(a*)*b", "(a|a)+b"] {
+        for length in [12, 16, 20] {
+            let text = "a".repeat(length);
+            for memoize in [false, true] {
+                let engine = RegexBuilder::new(pattern)
+                    .memoize(memoize)
+                    .backtrack_limit(usize::MAX)
+                    .build()
+                    .unwrap();
+                let started = Instant::now();
+                let found = engine.match_text(&text);
+                println!(
+                    "{:<10} length {:>2}  memoize {:<5}  matched {:<5}  {:?}",
+                    pattern,
+                    length,
+                    memoize,
+                    found,
+                    started.elapsed()
+                );
+            }
+        }
+    }
+}
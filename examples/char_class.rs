@@ -0,0 +1,15 @@
+// Times character classes over a long line, for comparing changes to class matching.
+// Run with: cargo run --release --example char_class
+use std::time::Instant;
+
+use mygrep::RegexEngine;
+
+fn main() {
+    let text = "The quick brown fox, 1234 jumps over the lazy dog; ".repeat(20_000);
+    for pattern in ["[a-zA-Z0-9]+", "[^a-z ]+", "[[:punct:]\\d]", "[à-ÿ]"] {
+        let engine = RegexEngine::new(pattern).unwrap();
+        let started = Instant::now();
+        let count = engine.find_iter(&text).count();
+        println!("{:<16} {:>7} matches  {:?}", pattern, count, started.elapsed());
+    }
+}
@@ -0,0 +1,19 @@
+// Times matching many short strings with a fresh search each time and with a reused MatchCache.
+// Run with: cargo run --release --example reuse
+use std::time::Instant;
+
+use mygrep::{MatchCache, RegexEngine};
+
+fn main() {
+    let engine = RegexEngine::new(r"(\w+)@(\w+)\.com").unwrap();
+    let texts: Vec<String> = (0..100_000).map(|i| format!("user{}@host{}.org", i, i % 7)).collect();
+
+    let started = Instant::now();
+    let fresh = texts.iter().filter(|text| engine.match_text(text)).count();
+    println!("match_text        matched {:>6}  {:?}", fresh, started.elapsed());
+
+    let started = Instant::now();
+    let mut cache = MatchCache::new();
+    let reused = texts.iter().filter(|text| engine.match_text_reuse(text, &mut cache)).count();
+    println!("match_text_reuse  matched {:>6}  {:?}", reused, started.elapsed());
+}
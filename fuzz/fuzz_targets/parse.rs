@@ -0,0 +1,19 @@
+// Parses arbitrary patterns. Invalid ones must be rejected with an Error, never a panic, and
+// printing a valid one must give a pattern that parses again.
+// Run with: cargo +nightly fuzz run parse
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use mygrep::RegexEngine;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(pattern) = std::str::from_utf8(data) else {
+        return;
+    };
+    if let Ok(engine) = RegexEngine::new(pattern) {
+        let printed = engine.to_string();
+        if let Err(err) = RegexEngine::new(&printed) {
+            panic!("{:?} prints as {:?}, which does not parse: {}", pattern, printed, err);
+        }
+    }
+});
@@ -0,0 +1,24 @@
+// Searches arbitrary text with arbitrary patterns. A low backtracking limit keeps slow patterns
+// from being reported as timeouts; running out of steps is an Error, not a bug.
+// Run with: cargo +nightly fuzz run search
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use mygrep::RegexBuilder;
+
+fuzz_target!(|input: (&str, &str)| {
+    let (pattern, text) = input;
+    let Ok(engine) = RegexBuilder::new(pattern).backtrack_limit(10_000).build() else {
+        return;
+    };
+    if let Ok(Some(caps)) = engine.try_captures(text) {
+        let whole = caps.whole();
+        assert!(whole.start() <= whole.end() && whole.end() <= text.len());
+        assert_eq!(&text[whole.start()..whole.end()], whole.as_str());
+    }
+    for m in engine.find_iter(text) {
+        assert!(text.is_char_boundary(m.start()) && text.is_char_boundary(m.end()));
+    }
+    let _ = engine.match_all_overlapping(text);
+    let _ = engine.replace_all(text, "$1");
+});
@@ -0,0 +1,70 @@
+// Line-by-line searching of a reader, the part of grep that does not depend on the command line
+use std::io::{self, BufRead};
+use std::iter;
+use std::ops::Range;
+
+use crate::RegexEngine;
+
+// Which lines grep_lines selects
+#[derive(Debug, Clone, Default)]
+pub struct GrepOptions {
+    pub invert: bool, // Select the lines that do not match instead of the ones that do
+}
+
+// A line selected by grep_lines
+#[derive(Debug, Clone, PartialEq)]
+pub struct GrepMatch {
+    pub line_number: usize,         // Counted from 1
+    pub line: String,               // Without its line break, with invalid UTF-8 replaced by U+FFFD
+    pub bytes: Vec<u8>,             // The line as it was read, also without its line break
+    pub matches: Vec<Range<usize>>, // Byte ranges of the matches in `line`; empty for inverted searches
+}
+
+// Reads the next line into `line`, without its "\n" or "\r\n", so '$' and \z both match at the end
+// of the line. Returns false at the end of the input.
+pub fn read_line(reader: &mut impl BufRead, line: &mut Vec<u8>) -> io::Result<bool> {
+    line.clear();
+    if reader.read_until(b'\n', line)? == 0 {
+        return Ok(false);
+    }
+    if line.ends_with(b"\n") {
+        line.pop();
+        if line.ends_with(b"\r") {
+            line.pop();
+        }
+    }
+    Ok(true)
+}
+
+// Reads `reader` one line at a time, as read_line does, and yields the lines that `engine`
+// selects. A line that is not valid UTF-8 is matched with the bad bytes replaced. A line that
+// cannot be read, or whose search runs past the backtracking or depth limit, yields the error and
+// the search carries on with the next one.
+pub fn grep_lines<'a, R: BufRead + 'a>(
+    engine: &'a RegexEngine,
+    mut reader: R,
+    options: &GrepOptions,
+) -> impl Iterator<Item = Result<GrepMatch, io::Error>> + 'a {
+    let invert = options.invert;
+    let mut line_number = 0;
+    iter::from_fn(move || loop {
+        let mut bytes = Vec::new();
+        match read_line(&mut reader, &mut bytes) {
+            Ok(true) => line_number += 1,
+            Ok(false) => return None,
+            Err(err) => return Some(Err(err)),
+        }
+        let line = String::from_utf8_lossy(&bytes).into_owned();
+        match engine.try_find(&line) {
+            Ok(found) if found.is_some() == invert => continue,
+            Ok(_) => {}
+            Err(err) => return Some(Err(io::Error::other(err))),
+        }
+        let matches = if invert {
+            Vec::new()
+        } else {
+            engine.find_iter(&line).map(|m| m.start()..m.end()).collect()
+        };
+        return Some(Ok(GrepMatch { line_number, line, bytes, matches }));
+    })
+}
@@ -0,0 +1,2986 @@
+// Without the std feature the engine only needs an allocator; grep_lines, which reads from
+// std::io, and the std::error::Error impl for Error are left out.
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::{Cell, RefCell};
+use core::fmt;
+use core::ops::Index;
+use core::str::FromStr;
+
+#[cfg(feature = "std")]
+mod grep;
+#[cfg(feature = "unicode-scripts")]
+mod scripts;
+
+#[cfg(feature = "std")]
+pub use grep::{grep_lines, read_line, GrepMatch, GrepOptions};
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+enum RE {
+    Char(char),                 // A literal character
+    Repeat {                    // Any quantifier: '?' is {0,1}, '+' is {1,} and '*' is {0,}, e.g., a{2,5}?
+        inner: Box<RE>,
+        min: usize,
+        max: Option<usize>,     // None for no upper bound
+        greediness: Greediness,
+    },
+    Dot,                        // The '.' metacharacter
+    Start,                      // The '^' metacharacter
+    End,                        // The '$' metacharacter (end of the text, or before a final '\n')
+    CharClass(CharClass),       // A character class, e.g., [a-z]
+    NegCharClass(CharClass),    // A negated character class, e.g., [^a-z]
+    Digit,                      // Shorthand for \d (any digit)
+    Word,                       // Shorthand for \w (letter, digit or '_')
+    NonDigit,                   // Shorthand for \D (any non-digit)
+    NonWord,                    // Shorthand for \W (anything but a letter, digit or '_')
+    Space,                      // Shorthand for \s (whitespace character)
+    NonSpace,                   // Shorthand for \S (non-whitespace character)
+    #[cfg_attr(not(feature = "unicode"), allow(dead_code))]
+    UnicodeProperty(UnicodeProperty, bool), // A general category, negated if the flag is set, e.g., \p{L} or \P{L}
+    Alternation(Vec<Vec<RE>>),  // Alternation between patterns, e.g., (cat|dog|bird)
+    Group(usize, Vec<RE>),      // A grouped sub-pattern with its 1-based capture index, e.g., (cat)
+    NonCapturingGroup(Vec<RE>), // A group that does not capture, e.g., (?:cat)
+    NamedGroup(usize, String, Vec<RE>), // A named capture group with its index, e.g., (?P<animal>cat)
+    AtomicGroup(Vec<RE>),       // A group that is never backtracked into, e.g., (?>a+)
+    LookaheadPos(Vec<RE>),      // A positive lookahead assertion, e.g., (?=cat)
+    LookaheadNeg(Vec<RE>),      // A negative lookahead assertion, e.g., (?!cat)
+    LookbehindPos(Vec<RE>),     // A positive lookbehind assertion, e.g., (?<=cat)
+    LookbehindNeg(Vec<RE>),     // A negative lookbehind assertion, e.g., (?<!cat)
+    Conditional { group: usize, yes: Vec<RE>, no: Vec<RE> }, // `yes` if the group took part in the match, else `no`, e.g., (?(1)a|b)
+    SetFlags(Flags),            // Switches the active flags from here on, e.g., (?i)
+    Backreference(usize),       // A backreference to a previously captured group, e.g., \1
+    NamedBackreference(String), // A backreference to a named group, e.g., \k<animal>
+    WordBoundary,               // The \b assertion
+    NonWordBoundary,            // The \B assertion
+    AbsoluteStart,              // The \A anchor (start of the whole input)
+    AbsoluteEnd,                // The \z anchor (end of the whole input)
+    AbsoluteEndOrNewline,       // The \Z anchor (end of the whole input, or before a final '\n')
+    KeepStart,                  // The \K assertion (the reported match starts here)
+    ContinuationAnchor,         // The \G anchor (where the previous match ended)
+}
+
+// The items of a bracket expression as written, plus a bitmask of the ASCII characters they
+// contain, so that testing an ASCII character is a single lookup instead of a scan
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct CharClass {
+    items: Vec<ClassItem>,
+    ascii: [u64; 2], // Bit c % 64 of ascii[c / 64] is set when ASCII character c is in the class
+}
+
+impl CharClass {
+    fn new(items: Vec<ClassItem>) -> Self {
+        let mut ascii = [0; 2];
+        // Every flag that changes class membership only does so outside ASCII
+        let flags = Flags::default();
+        for c in 0..128u8 {
+            if items.iter().any(|item| item.contains(c as char, flags)) {
+                ascii[usize::from(c / 64)] |= 1 << (c % 64);
+            }
+        }
+        CharClass { items, ascii }
+    }
+
+    // Whether the class contains `c`, without regard to case-insensitivity
+    fn contains(&self, c: char, flags: Flags) -> bool {
+        if c.is_ascii() {
+            self.ascii[c as usize / 64] & (1 << (c as u32 % 64)) != 0
+        } else {
+            self.items.iter().any(|item| item.contains(c, flags))
+        }
+    }
+}
+
+// A single entry inside a character class
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+enum ClassItem {
+    Char(char),        // A literal character, e.g., the 'a' in [abc]
+    Range(char, char), // An inclusive range, e.g., [a-z]
+    Shorthand(RE),     // A shorthand class such as \d, \w or \s, e.g., [\d_]
+    Posix(PosixClass), // A POSIX named class, e.g., [[:alpha:]]
+}
+
+impl ClassItem {
+    fn contains(&self, c: char, flags: Flags) -> bool {
+        match self {
+            ClassItem::Char(ch) => *ch == c,
+            ClassItem::Range(start, end) => (*start..=*end).contains(&c),
+            ClassItem::Shorthand(re) => matches_shorthand(re, c, flags),
+            ClassItem::Posix(posix) => posix.matches(c),
+        }
+    }
+}
+
+// Whether `c` is matched by one of the shorthand classes \d, \D, \w, \W, \s and \S
+fn matches_shorthand(re: &RE, c: char, flags: Flags) -> bool {
+    match re {
+        RE::Digit => c.is_ascii_digit(),
+        RE::Word => is_word_char(c, flags),
+        RE::NonDigit => !c.is_ascii_digit(),
+        RE::NonWord => !is_word_char(c, flags),
+        RE::Space => is_space_char(c, flags),
+        RE::NonSpace => !is_space_char(c, flags),
+        RE::UnicodeProperty(property, negated) => property.matches(c) != *negated,
+        _ => false,
+    }
+}
+
+// The named classes usable as [:name:] inside a bracket expression
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+enum PosixClass {
+    Alpha,
+    Digit,
+    Alnum,
+    Upper,
+    Lower,
+    Space,
+    Blank,
+    Punct,
+    Print,
+    Graph,
+    Cntrl,
+    Xdigit,
+}
+
+impl PosixClass {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "alpha" => Some(PosixClass::Alpha),
+            "digit" => Some(PosixClass::Digit),
+            "alnum" => Some(PosixClass::Alnum),
+            "upper" => Some(PosixClass::Upper),
+            "lower" => Some(PosixClass::Lower),
+            "space" => Some(PosixClass::Space),
+            "blank" => Some(PosixClass::Blank),
+            "punct" => Some(PosixClass::Punct),
+            "print" => Some(PosixClass::Print),
+            "graph" => Some(PosixClass::Graph),
+            "cntrl" => Some(PosixClass::Cntrl),
+            "xdigit" => Some(PosixClass::Xdigit),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            PosixClass::Alpha => "alpha",
+            PosixClass::Digit => "digit",
+            PosixClass::Alnum => "alnum",
+            PosixClass::Upper => "upper",
+            PosixClass::Lower => "lower",
+            PosixClass::Space => "space",
+            PosixClass::Blank => "blank",
+            PosixClass::Punct => "punct",
+            PosixClass::Print => "print",
+            PosixClass::Graph => "graph",
+            PosixClass::Cntrl => "cntrl",
+            PosixClass::Xdigit => "xdigit",
+        }
+    }
+
+    fn matches(self, c: char) -> bool {
+        match self {
+            PosixClass::Alpha => c.is_alphabetic(),
+            PosixClass::Digit => c.is_ascii_digit(),
+            PosixClass::Alnum => c.is_alphanumeric(),
+            PosixClass::Upper => c.is_uppercase(),
+            PosixClass::Lower => c.is_lowercase(),
+            PosixClass::Space => c.is_whitespace(),
+            PosixClass::Blank => c == ' ' || c == '\t',
+            PosixClass::Punct => c.is_ascii_punctuation(),
+            PosixClass::Print => !c.is_control(),
+            PosixClass::Graph => !c.is_control() && !c.is_whitespace(),
+            PosixClass::Cntrl => c.is_control(),
+            PosixClass::Xdigit => c.is_ascii_hexdigit(),
+        }
+    }
+}
+
+// The Unicode properties usable as \p{name}: general categories by their short or long name,
+// and with the unicode-scripts feature, scripts and blocks as in \p{Script=Greek}. The standard
+// library has no category tables, so each category is decided by the closest char method; see
+// matches. Without the unicode feature none can be parsed, but the rest of the engine still
+// knows them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(not(feature = "unicode"), allow(dead_code))]
+// Script and block tables are static, so properties are saved by name and looked up on loading
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(into = "String", try_from = "String"))]
+enum UnicodeProperty {
+    Letter,
+    UppercaseLetter,
+    LowercaseLetter,
+    Number,
+    Punctuation,
+    Separator,
+    Control,
+    #[cfg(feature = "unicode-scripts")]
+    Script(&'static scripts::NamedRanges),
+    #[cfg(feature = "unicode-scripts")]
+    Block(&'static scripts::NamedRanges),
+}
+
+#[cfg_attr(not(feature = "unicode"), allow(dead_code))]
+impl UnicodeProperty {
+    fn from_name(name: &str) -> Option<Self> {
+        #[cfg(feature = "unicode-scripts")]
+        if let Some((kind, value)) = name.split_once('=') {
+            return match kind {
+                "Script" | "sc" => scripts::script(value).map(UnicodeProperty::Script),
+                "Block" | "blk" => scripts::block(value).map(UnicodeProperty::Block),
+                _ => None,
+            };
+        }
+        match name {
+            "L" | "Letter" => Some(UnicodeProperty::Letter),
+            "Lu" | "Uppercase_Letter" => Some(UnicodeProperty::UppercaseLetter),
+            "Ll" | "Lowercase_Letter" => Some(UnicodeProperty::LowercaseLetter),
+            "N" | "Number" => Some(UnicodeProperty::Number),
+            "P" | "Punctuation" => Some(UnicodeProperty::Punctuation),
+            "Z" | "Separator" => Some(UnicodeProperty::Separator),
+            "Cc" | "Control" => Some(UnicodeProperty::Control),
+            _ => None,
+        }
+    }
+
+    fn matches(self, c: char) -> bool {
+        match self {
+            UnicodeProperty::Letter => c.is_alphabetic(),
+            UnicodeProperty::UppercaseLetter => c.is_uppercase(),
+            UnicodeProperty::LowercaseLetter => c.is_lowercase(),
+            UnicodeProperty::Number => c.is_numeric(),
+            // ASCII punctuation less the characters Unicode counts as symbols, then the
+            // punctuation in Latin-1, the General Punctuation block, CJK and fullwidth forms
+            UnicodeProperty::Punctuation => matches!(
+                c,
+                '!'..='#'
+                    | '%'..='*'
+                    | ','..='/'
+                    | ':'
+                    | ';'
+                    | '?'
+                    | '@'
+                    | '['..=']'
+                    | '_'
+                    | '{'
+                    | '}'
+                    | '\u{A1}'
+                    | '\u{A7}'
+                    | '\u{AB}'
+                    | '\u{B6}'
+                    | '\u{B7}'
+                    | '\u{BB}'
+                    | '\u{BF}'
+                    | '\u{2010}'..='\u{2027}'
+                    | '\u{2030}'..='\u{2043}'
+                    | '\u{2045}'..='\u{2051}'
+                    | '\u{2053}'..='\u{205E}'
+                    | '\u{3001}'..='\u{3003}'
+                    | '\u{3008}'..='\u{3011}'
+                    | '\u{3014}'..='\u{301F}'
+                    | '\u{FF01}'..='\u{FF03}'
+                    | '\u{FF05}'..='\u{FF0A}'
+                    | '\u{FF0C}'..='\u{FF0F}'
+                    | '\u{FF1A}'
+                    | '\u{FF1B}'
+                    | '\u{FF1F}'
+                    | '\u{FF20}'
+            ),
+            // Whitespace other than the control characters such as '\t' and '\n'
+            UnicodeProperty::Separator => c.is_whitespace() && !c.is_control(),
+            UnicodeProperty::Control => c.is_control(),
+            #[cfg(feature = "unicode-scripts")]
+            UnicodeProperty::Script(ranges) | UnicodeProperty::Block(ranges) => ranges.contains(c),
+        }
+    }
+}
+
+// Writes the name used between the braces of \p{..}
+impl fmt::Display for UnicodeProperty {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UnicodeProperty::Letter => f.write_str("L"),
+            UnicodeProperty::UppercaseLetter => f.write_str("Lu"),
+            UnicodeProperty::LowercaseLetter => f.write_str("Ll"),
+            UnicodeProperty::Number => f.write_str("N"),
+            UnicodeProperty::Punctuation => f.write_str("P"),
+            UnicodeProperty::Separator => f.write_str("Z"),
+            UnicodeProperty::Control => f.write_str("Cc"),
+            #[cfg(feature = "unicode-scripts")]
+            UnicodeProperty::Script(script) => write!(f, "Script={}", script.name),
+            #[cfg(feature = "unicode-scripts")]
+            UnicodeProperty::Block(block) => write!(f, "Block={}", block.name),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<UnicodeProperty> for String {
+    fn from(property: UnicodeProperty) -> String {
+        property.to_string()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<String> for UnicodeProperty {
+    type Error = Error;
+
+    fn try_from(name: String) -> Result<Self, Error> {
+        UnicodeProperty::from_name(&name).ok_or(Error::UnknownProperty(name))
+    }
+}
+
+// Writes the pattern syntax for a node; parsing the output gives back an equivalent node
+impl fmt::Display for RE {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RE::Char(c) => write_char(f, *c, "\\.^$|?*+()[{"),
+            RE::Repeat { inner, min, max, greediness } => write_quantified(f, inner, &repeat_syntax(*min, *max, *greediness)),
+            RE::Dot => f.write_str("."),
+            RE::Start => f.write_str("^"),
+            RE::End => f.write_str("$"),
+            RE::CharClass(class) => write_class(f, "[", &class.items),
+            RE::NegCharClass(class) => write_class(f, "[^", &class.items),
+            RE::Digit => f.write_str("\\d"),
+            RE::Word => f.write_str("\\w"),
+            RE::NonDigit => f.write_str("\\D"),
+            RE::NonWord => f.write_str("\\W"),
+            RE::Space => f.write_str("\\s"),
+            RE::NonSpace => f.write_str("\\S"),
+            RE::UnicodeProperty(property, negated) => {
+                write!(f, "\\{}{{{}}}", if *negated { 'P' } else { 'p' }, property)
+            }
+            RE::Alternation(alternatives) => {
+                for (i, alternative) in alternatives.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str("|")?;
+                    }
+                    write_sequence(f, alternative)?;
+                }
+                Ok(())
+            }
+            RE::Group(_, inner) => write_group(f, "(", inner),
+            RE::NonCapturingGroup(inner) => write_group(f, "(?:", inner),
+            RE::NamedGroup(_, name, inner) => write_group(f, &format!("(?P<{}>", name), inner),
+            RE::AtomicGroup(inner) => write_group(f, "(?>", inner),
+            RE::LookaheadPos(inner) => write_group(f, "(?=", inner),
+            RE::LookaheadNeg(inner) => write_group(f, "(?!", inner),
+            RE::LookbehindPos(inner) => write_group(f, "(?<=", inner),
+            RE::LookbehindNeg(inner) => write_group(f, "(?<!", inner),
+            RE::Conditional { group, yes, no } => {
+                write!(f, "(?({})", group)?;
+                write_sequence(f, yes)?;
+                if !no.is_empty() {
+                    f.write_str("|")?;
+                    write_sequence(f, no)?;
+                }
+                f.write_str(")")
+            }
+            RE::SetFlags(flags) => {
+                // The node holds the complete set of flags, so every flag is written out
+                let letters = [
+                    ('i', flags.case_insensitive),
+                    ('m', flags.multiline),
+                    ('s', flags.dotall),
+                    ('x', flags.free_spacing),
+                ];
+                let on: String = letters.iter().filter(|(_, set)| *set).map(|(c, _)| *c).collect();
+                let off: String = letters.iter().filter(|(_, set)| !*set).map(|(c, _)| *c).collect();
+                if off.is_empty() {
+                    write!(f, "(?{})", on)
+                } else {
+                    write!(f, "(?{}-{})", on, off)
+                }
+            }
+            RE::Backreference(index) => write!(f, "\\{}", index),
+            RE::NamedBackreference(name) => write!(f, "\\k<{}>", name),
+            RE::WordBoundary => f.write_str("\\b"),
+            RE::NonWordBoundary => f.write_str("\\B"),
+            RE::AbsoluteStart => f.write_str("\\A"),
+            RE::AbsoluteEnd => f.write_str("\\z"),
+            RE::AbsoluteEndOrNewline => f.write_str("\\Z"),
+            RE::KeepStart => f.write_str("\\K"),
+            RE::ContinuationAnchor => f.write_str("\\G"),
+        }
+    }
+}
+
+impl fmt::Display for ClassItem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClassItem::Char(c) => write_char(f, *c, "\\[]^-"),
+            ClassItem::Range(start, end) => {
+                write_char(f, *start, "\\[]^-")?;
+                f.write_str("-")?;
+                write_char(f, *end, "\\[]^-")
+            }
+            ClassItem::Shorthand(re) => write!(f, "{}", re),
+            ClassItem::Posix(posix) => write!(f, "[:{}:]", posix.name()),
+        }
+    }
+}
+
+fn write_sequence(f: &mut fmt::Formatter, pattern: &[RE]) -> fmt::Result {
+    for (i, re) in pattern.iter().enumerate() {
+        // A digit right after a backreference would read as part of its group number, whether it
+        // is a literal or starts a quantified one like 0+
+        let digit_next = pattern.get(i + 1).is_some_and(|next| next.to_string().starts_with(|c: char| c.is_ascii_digit()));
+        match re {
+            RE::Backreference(_) if digit_next => write!(f, "(?:{})", re)?,
+            _ => write!(f, "{}", re)?,
+        }
+    }
+    Ok(())
+}
+
+fn write_group(f: &mut fmt::Formatter, open: &str, inner: &[RE]) -> fmt::Result {
+    f.write_str(open)?;
+    write_sequence(f, inner)?;
+    f.write_str(")")
+}
+
+fn write_class(f: &mut fmt::Formatter, open: &str, class: &[ClassItem]) -> fmt::Result {
+    f.write_str(open)?;
+    class.iter().try_for_each(|item| write!(f, "{}", item))?;
+    f.write_str("]")
+}
+
+// Writes a quantifier after its operand. A quantified quantifier gets a group around it, since
+// a suffix such as '?' directly after '*' would read as a laziness marker instead.
+fn write_quantified(f: &mut fmt::Formatter, inner: &RE, suffix: &str) -> fmt::Result {
+    match inner {
+        RE::Repeat { .. } => write!(f, "(?:{}){}", inner, suffix),
+        _ => write!(f, "{}{}", inner, suffix),
+    }
+}
+
+// The shortest way of writing a quantifier
+fn repeat_syntax(min: usize, max: Option<usize>, greediness: Greediness) -> String {
+    let counts = match (min, max) {
+        (0, Some(1)) => "?".to_string(),
+        (1, None) => "+".to_string(),
+        (0, None) => "*".to_string(),
+        (min, Some(max)) if max == min => format!("{{{}}}", min),
+        (min, Some(max)) => format!("{{{},{}}}", min, max),
+        (min, None) => format!("{{{},}}", min),
+    };
+    match greediness {
+        Greediness::Greedy => counts,
+        Greediness::Lazy => counts + "?",
+        Greediness::Possessive => counts + "+",
+    }
+}
+
+// Writes a literal character, escaping it if it is one of `specials` or could otherwise be
+// misread: whitespace or '#' (both ignored in free-spacing mode) or a control character.
+fn write_char(f: &mut fmt::Formatter, c: char, specials: &str) -> fmt::Result {
+    match c {
+        ' ' => f.write_str("\\ "),
+        '#' => f.write_str("\\#"),
+        '\t' => f.write_str("\\t"),
+        '\n' => f.write_str("\\n"),
+        '\r' => f.write_str("\\r"),
+        '\x07' => f.write_str("\\a"),
+        '\x0C' => f.write_str("\\f"),
+        '\x1B' => f.write_str("\\e"),
+        c if specials.contains(c) => write!(f, "\\{}", c),
+        c if c.is_control() || c.is_whitespace() => {
+            if (c as u32) < 0x100 {
+                write!(f, "\\x{:02X}", c as u32)
+            } else {
+                write!(f, "\\u{:04X}", c as u32)
+            }
+        }
+        c => write!(f, "{}", c),
+    }
+}
+
+// Adds a node labelled `label` to a DOT graph, with an edge to each node of `pattern`, and
+// returns its id. `next_id` is the id the next node gets.
+fn dot_sequence(dot: &mut String, next_id: &mut usize, label: &str, pattern: &[RE]) -> usize {
+    let id = dot_node(dot, next_id, label);
+    dot_edges(dot, next_id, id, pattern);
+    id
+}
+
+fn dot_edges(dot: &mut String, next_id: &mut usize, from: usize, pattern: &[RE]) {
+    for (i, re) in pattern.iter().enumerate() {
+        let to = dot_re(dot, next_id, re);
+        dot.push_str(&format!("    n{} -> n{} [label=\"{}\"];\n", from, to, i + 1));
+    }
+}
+
+// Adds the node for `re` and everything inside it, and returns its id. Nodes are labelled with
+// their variant, plus the pattern syntax for nodes without parts of their own.
+fn dot_re(dot: &mut String, next_id: &mut usize, re: &RE) -> usize {
+    let name: String = format!("{:?}", re).chars().take_while(char::is_ascii_alphanumeric).collect();
+    let label = match re {
+        RE::Repeat { min, max, greediness, .. } => format!("{} {}", name, repeat_syntax(*min, *max, *greediness)),
+        RE::Group(index, _) => format!("{} {}", name, index),
+        RE::NamedGroup(index, group_name, _) => format!("{} {} <{}>", name, index, group_name),
+        RE::Conditional { group, .. } => format!("{} on group {}", name, group),
+        RE::Alternation(_)
+        | RE::NonCapturingGroup(_)
+        | RE::AtomicGroup(_)
+        | RE::LookaheadPos(_)
+        | RE::LookaheadNeg(_)
+        | RE::LookbehindPos(_)
+        | RE::LookbehindNeg(_) => name,
+        _ => format!("{}\n{}", name, re),
+    };
+    let id = dot_node(dot, next_id, &label);
+    match re {
+        RE::Repeat { inner, .. } => dot_edges(dot, next_id, id, core::slice::from_ref(inner.as_ref())),
+        RE::Group(_, inner)
+        | RE::NamedGroup(_, _, inner)
+        | RE::NonCapturingGroup(inner)
+        | RE::AtomicGroup(inner)
+        | RE::LookaheadPos(inner)
+        | RE::LookaheadNeg(inner)
+        | RE::LookbehindPos(inner)
+        | RE::LookbehindNeg(inner) => dot_edges(dot, next_id, id, inner),
+        RE::Alternation(alternatives) => {
+            for (i, alternative) in alternatives.iter().enumerate() {
+                let to = dot_sequence(dot, next_id, &format!("Alternative {}", i + 1), alternative);
+                dot.push_str(&format!("    n{} -> n{};\n", id, to));
+            }
+        }
+        RE::Conditional { yes, no, .. } => {
+            for (branch, pattern) in [("yes", yes), ("no", no)] {
+                let to = dot_sequence(dot, next_id, branch, pattern);
+                dot.push_str(&format!("    n{} -> n{} [label=\"{}\"];\n", id, to, branch));
+            }
+        }
+        _ => {}
+    }
+    id
+}
+
+fn dot_node(dot: &mut String, next_id: &mut usize, label: &str) -> usize {
+    let id = *next_id;
+    *next_id += 1;
+    let label = label.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+    dot.push_str(&format!("    n{} [label=\"{}\"];\n", id, label));
+    id
+}
+
+// How a quantifier chooses between its possible repetition counts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+enum Greediness {
+    Greedy,     // Longest run first, backing off on failure
+    Lazy,       // Shortest run first, extending on failure
+    Possessive, // Longest run only, never backing off
+}
+
+// The characters \w matches and \b looks for: letters, digits and '_', either from all of
+// Unicode or only from ASCII
+fn is_word_char(c: char, flags: Flags) -> bool {
+    c == '_' || (c.is_alphanumeric() && (flags.unicode || c.is_ascii()))
+}
+
+// The characters \s matches: whitespace from all of Unicode, such as the no-break space U+00A0,
+// or only the ASCII space, tab, '\n', '\r', vertical tab and form feed
+fn is_space_char(c: char, flags: Flags) -> bool {
+    c.is_whitespace() && (flags.unicode || c.is_ascii())
+}
+
+// Simple case folding: the character `c` stands for when case is ignored, so that 'K', 'k' and
+// the Kelvin sign all fold to 'k', and 'Σ', 'σ' and 'ς' to 'σ'. Characters whose case mapping is
+// several characters long, such as 'ß', fold to themselves; see full_fold for those.
+fn fold_case(c: char) -> char {
+    let upper = single_char(c.to_uppercase()).unwrap_or(c);
+    single_char(upper.to_lowercase()).or_else(|| single_char(c.to_lowercase())).unwrap_or(c)
+}
+
+// The characters that fold to several characters, which the standard library's case mappings
+// only hint at
+fn full_fold(c: char) -> Option<&'static str> {
+    match c {
+        'ß' | 'ẞ' => Some("ss"),
+        'ﬀ' => Some("ff"),
+        'ﬁ' => Some("fi"),
+        'ﬂ' => Some("fl"),
+        'ﬃ' => Some("ffi"),
+        'ﬄ' => Some("ffl"),
+        'ﬅ' | 'ﬆ' => Some("st"),
+        _ => None,
+    }
+}
+
+// Whether `re` always matches exactly one character
+fn is_single_char(re: &RE) -> bool {
+    matches!(
+        re,
+        RE::Char(_)
+            | RE::Dot
+            | RE::Digit
+            | RE::Word
+            | RE::NonDigit
+            | RE::NonWord
+            | RE::Space
+            | RE::NonSpace
+            | RE::UnicodeProperty(..)
+            | RE::CharClass(_)
+            | RE::NegCharClass(_)
+    )
+}
+
+// The only character of a case mapping, or None if it has several
+fn single_char(mut chars: impl Iterator<Item = char>) -> Option<char> {
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
+// Matching options that can be set up front with RegexBuilder or inline with (?flags)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Flags {
+    pub case_insensitive: bool, // i: letters match regardless of case, including outside ASCII
+    pub multiline: bool,        // m: '^' and '$' also match at the start and end of each line
+    pub dotall: bool,           // s: '.' also matches '\n'
+    pub free_spacing: bool,     // x: unescaped whitespace and '#' comments in the pattern are ignored
+    // \w, \b and \s go by Unicode rather than only ASCII; on by default. Flags saved while it was
+    // called unicode_word still load.
+    #[cfg_attr(feature = "serde", serde(alias = "unicode_word"))]
+    pub unicode: bool,
+}
+
+impl Default for Flags {
+    fn default() -> Self {
+        Flags {
+            case_insensitive: false,
+            multiline: false,
+            dotall: false,
+            free_spacing: false,
+            unicode: true,
+        }
+    }
+}
+
+impl Flags {
+    // The old name of the unicode field, from when it only covered \w and \b
+    #[deprecated(note = "use the `unicode` field, which also covers \\s")]
+    pub fn unicode_word(&self) -> bool {
+        self.unicode
+    }
+
+    // Applies inline flag letters such as "i" or "-i", returning false on an unknown letter
+    fn apply(&mut self, spec: &[char]) -> bool {
+        let mut enable = true;
+        for &c in spec {
+            match c {
+                '-' => enable = false,
+                'i' => self.case_insensitive = enable,
+                'm' => self.multiline = enable,
+                's' => self.dotall = enable,
+                'x' => self.free_spacing = enable,
+                _ => return false,
+            }
+        }
+        true
+    }
+}
+
+// Everything that can make a pattern invalid
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    UnsupportedEscape(char),
+    TrailingBackslash,
+    InvalidHexEscape(char, usize),
+    InvalidCodePoint(char, u32),
+    UnterminatedClass,
+    InvalidClassRange,
+    UnknownPosixClass(String),
+    InvalidBackreference(usize),
+    InvalidNamedBackreference(String),
+    UnknownProperty(String),
+    UnmatchedParen,
+    EmptyQuantifier(char),
+    InvalidRepeatCount(usize, usize),
+    InvalidGroupName,
+    DuplicateGroupName(String),
+    UnsupportedFlag(String),
+    InvalidConditionGroup(usize),
+    TooManyConditionalBranches,
+    VariableLookbehind,
+    BacktrackLimitExceeded,
+    StackDepthExceeded,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::UnsupportedEscape(c) => write!(f, "unsupported escape sequence: \\{}", c),
+            Error::TrailingBackslash => f.write_str("pattern ends with an incomplete escape sequence"),
+            Error::InvalidHexEscape(c, digits) => write!(f, "escape sequence \\{} requires {} hex digits", c, digits),
+            Error::InvalidCodePoint(c, value) => write!(f, "escape sequence \\{} is not a valid character: {:X}", c, value),
+            Error::UnterminatedClass => f.write_str("unterminated character class"),
+            Error::InvalidClassRange => f.write_str("invalid range in character class"),
+            Error::UnknownPosixClass(name) => write!(f, "unknown POSIX class: [:{}:]", name),
+            Error::InvalidBackreference(index) => write!(f, "backreference \\{} refers to a group that does not exist", index),
+            Error::InvalidNamedBackreference(name) => write!(f, "backreference \\k<{}> refers to a group that does not exist", name),
+            Error::UnknownProperty(name) => write!(f, "unknown Unicode property: {}", name),
+            Error::UnmatchedParen => f.write_str("unmatched parenthesis"),
+            Error::EmptyQuantifier(c) => write!(f, "'{}' has nothing to repeat", c),
+            Error::InvalidRepeatCount(min, max) => write!(f, "invalid repeat count: {{{},{}}}", min, max),
+            Error::InvalidGroupName => f.write_str("group names must be written as <name> and cannot be empty"),
+            Error::DuplicateGroupName(name) => write!(f, "group name <{}> is used more than once", name),
+            Error::UnsupportedFlag(flags) => write!(f, "unsupported inline flag in (?{})", flags),
+            Error::InvalidConditionGroup(group) => write!(f, "conditional refers to group {}, which does not exist", group),
+            Error::TooManyConditionalBranches => f.write_str("a conditional can only have a yes and a no branch"),
+            Error::VariableLookbehind => f.write_str("lookbehind assertions must have a fixed length"),
+            Error::BacktrackLimitExceeded => f.write_str("the match took more than the backtracking limit of steps"),
+            Error::StackDepthExceeded => f.write_str("the pattern or match nests deeper than the depth limit"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+// The kind of group being parsed, decided by the syntax right after its '('
+enum GroupKind {
+    Capturing(usize),
+    NonCapturing,
+    Named(usize, String),
+    Atomic,
+    LookaheadPos,
+    LookaheadNeg,
+    LookbehindPos,
+    LookbehindNeg,
+    Conditional(usize),
+}
+
+// What is left to match once the current pattern slice has been matched. Group bodies are
+// matched with a continuation, so the end of the group's match is known exactly instead of
+// being found by trying every slice of the remaining text.
+enum Continuation<'c> {
+    Done,                                 // Nothing is left; the overall match succeeds
+    Then(&'c [RE], &'c Continuation<'c>), // The rest of an enclosing sequence
+    CloseGroup {                          // The end of a capturing group's body
+        index: usize,
+        name: Option<&'c str>,
+        start: usize, // Byte offset where the group's match began
+        next: &'c Continuation<'c>,
+    },
+    Repeat {                              // The end of one repetition of a quantified sub-pattern
+        re: &'c RE,
+        min: usize,
+        max: Option<usize>,
+        greediness: Greediness,
+        count: usize, // Repetitions matched so far, including the one that just ended
+        start: usize, // Byte offset where that repetition began
+        next: &'c Continuation<'c>,
+    },
+}
+
+impl Continuation<'_> {
+    // Everything about the continuation chain that can change how a match ends, as a flat list.
+    // Sub-patterns are identified by their address, which stays put for the whole search.
+    fn fingerprint(&self, key: &mut Vec<usize>) {
+        match self {
+            Continuation::Done => {}
+            Continuation::Then(rest, next) => {
+                key.extend([1, rest.as_ptr() as usize, rest.len()]);
+                next.fingerprint(key);
+            }
+            Continuation::CloseGroup { index, start, next, .. } => {
+                key.extend([2, *index, *start]);
+                next.fingerprint(key);
+            }
+            Continuation::Repeat { re, min, max, greediness, count, start, next } => {
+                // Past the minimum, an unbounded repetition behaves the same whatever its count
+                let count = if max.is_none() { (*count).min(*min) } else { *count };
+                key.extend([3, *re as *const RE as usize, count, *start, *greediness as usize]);
+                next.fingerprint(key);
+            }
+        }
+    }
+}
+
+// Identifies a call to match_here: the pattern slice, the flags, the position and the
+// continuation's fingerprint. Captures are left out since only backreferences look at them, and
+// patterns with backreferences are not memoized.
+type MemoKey = (usize, usize, Flags, usize, Vec<usize>);
+
+// Hashing is faster, but without std only the ordered set is available
+#[cfg(feature = "std")]
+type FailureSet = std::collections::HashSet<MemoKey>;
+#[cfg(not(feature = "std"))]
+type FailureSet = alloc::collections::BTreeSet<MemoKey>;
+
+// Memory that searches can keep between them, passed to RegexEngine::match_text_reuse. A cache
+// can be used with any engine.
+#[derive(Debug, Default)]
+pub struct MatchCache {
+    failures: FailureSet,
+}
+
+impl MatchCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+// What every clone of a MatchContext shares during one search
+struct SearchState {
+    // How many more calls to match_here the search may make, and whether an attempt was cut
+    // short for want of them. Steps spent on attempts that failed still count.
+    steps_left: Cell<usize>,
+    steps_exceeded: Cell<bool>,
+    // How deeply match_here may recurse, and whether the search had to stop there
+    depth_limit: usize,
+    depth_exceeded: Cell<bool>,
+    // The calls to match_here known to fail. None when the pattern has backreferences, whose
+    // outcome depends on the captures.
+    failures: Option<RefCell<FailureSet>>,
+}
+
+#[derive(Clone)]
+struct MatchContext<'a> {
+    input: &'a str, // The whole input; `text` is always a slice of it
+    text: &'a str,
+    captures: BTreeMap<usize, String>,
+    named_captures: BTreeMap<String, String>,
+    flags: Flags,
+    search: &'a SearchState,
+    depth: usize,              // How many calls to match_here are in progress
+    kept_start: Option<usize>, // Where the last \K that matched moved the start of the match to
+    last_match_end: usize,     // Where the previous match ended, for \G; the search start if none
+}
+
+impl<'a> MatchContext<'a> {
+    fn new(text: &'a str, flags: Flags, search: &'a SearchState) -> Self {
+        Self {
+            input: text,
+            text,
+            captures: BTreeMap::new(),
+            named_captures: BTreeMap::new(),
+            flags,
+            search,
+            depth: 0,
+            kept_start: None,
+            last_match_end: 0,
+        }
+    }
+
+    // Readies the context for a new attempt at the start of `text`, forgetting any captures
+    fn reset(&mut self, text: &'a str) {
+        self.text = text;
+        self.captures.clear();
+        self.named_captures.clear();
+        self.kept_start = None;
+    }
+
+    // Finds the leftmost match of `pattern`, returning its start and end byte offsets
+    fn match_pattern(&mut self, pattern: &[RE]) -> Option<(usize, usize)> {
+        if let (Some(RE::Start), false) = (pattern.first(), self.flags.multiline) {
+            let start = self.offset();
+            if start == 0 && self.match_here(&pattern[1..], &Continuation::Done) {
+                return Some((self.kept_start.unwrap_or(start), self.offset()));
+            }
+            None
+        } else {
+            let mut text_slice = self.text;
+            // A failed attempt leaves the context as it found it, so one copy serves every start
+            let mut local_context = self.clone();
+            loop {
+                local_context.reset(text_slice);
+
+                if local_context.match_here(pattern, &Continuation::Done) {
+                    // On success the context has been left at the end of the match
+                    let start = self.input.len() - text_slice.len();
+                    *self = local_context; // Update self with successful match state
+                    return Some((self.kept_start.unwrap_or(start), self.offset()));
+                }
+
+                if text_slice.is_empty() {
+                    break;
+                }
+                text_slice = &text_slice[text_slice.chars().next().map_or(0, char::len_utf8)..];
+            }
+            None
+        }
+    }
+
+    // Matches `pattern` and then `next` at the current position. Every arm works on a clone and
+    // only copies it back once the whole continuation has matched, so a failed attempt never
+    // leaves captures, flags or position behind in `self`.
+    fn match_here(&mut self, pattern: &[RE], next: &Continuation) -> bool {
+        // Recursing any deeper could overflow the stack, so give up on the search instead
+        if self.depth >= self.search.depth_limit {
+            self.search.depth_exceeded.set(true);
+            return false;
+        }
+        self.depth += 1;
+        let matched = self.match_memoized(pattern, next);
+        self.depth -= 1;
+        matched
+    }
+
+    // Skips calls to match_here that are known to fail, and remembers the ones that do
+    fn match_memoized(&mut self, pattern: &[RE], next: &Continuation) -> bool {
+        let Some(failures) = &self.search.failures else {
+            return self.match_step(pattern, next);
+        };
+        let mut fingerprint = Vec::new();
+        next.fingerprint(&mut fingerprint);
+        let key = (pattern.as_ptr() as usize, pattern.len(), self.flags, self.offset(), fingerprint);
+        if failures.borrow().contains(&key) {
+            return false;
+        }
+        let matched = self.match_step(pattern, next);
+        // A failure caused by hitting a limit says nothing about the pattern
+        if !matched && self.search.steps_left.get() > 0 && !self.search.depth_exceeded.get() {
+            failures.borrow_mut().insert(key);
+        }
+        matched
+    }
+
+    // The body of match_here, without the limits and memoization
+    fn match_step(&mut self, pattern: &[RE], next: &Continuation) -> bool {
+        // Once the budget is spent every attempt fails, which quickly unwinds the search
+        match self.search.steps_left.get() {
+            0 => {
+                self.search.steps_exceeded.set(true);
+                return false;
+            }
+            steps => self.search.steps_left.set(steps - 1),
+        }
+        if pattern.is_empty() {
+            return match next {
+                Continuation::Done => true,
+                Continuation::Then(rest, next) => self.match_here(rest, next),
+                Continuation::CloseGroup { index, name, start, next } => {
+                    // The group's body matched, so capture its content
+                    let captured = self.input[*start..self.offset()].to_string();
+                    let mut local_context = self.clone();
+                    if let Some(name) = name {
+                        local_context.named_captures.insert(name.to_string(), captured.clone());
+                    }
+                    local_context.captures.insert(*index, captured);
+                    if local_context.match_here(&[], next) {
+                        *self = local_context;
+                        return true;
+                    }
+                    false
+                }
+                Continuation::Repeat { re, min, max, greediness, count, start, next } => {
+                    // A repetition that matched nothing would match nothing forever, so stop here
+                    if *count >= *min && self.offset() == *start {
+                        return self.match_here(&[], next);
+                    }
+                    self.match_repetitions(re, *min, *max, *greediness, *count, next)
+                }
+            };
+        }
+
+        match &pattern[0] {
+            RE::Start => {
+                let at_line_start = self.offset() == 0 || (self.flags.multiline && self.prev_char() == Some('\n'));
+                at_line_start && self.match_here(&pattern[1..], next)
+            }
+            RE::End => {
+                // Like \Z, '$' also matches before a newline that ends the text
+                let at_line_end =
+                    self.text.is_empty() || self.text == "\n" || (self.flags.multiline && self.text.starts_with('\n'));
+                at_line_end && self.match_here(&pattern[1..], next)
+            }
+            RE::Char(_)
+            | RE::Dot
+            | RE::CharClass(_)
+            | RE::NegCharClass(_)
+            | RE::Digit
+            | RE::Word
+            | RE::NonDigit
+            | RE::NonWord
+            | RE::Space
+            | RE::NonSpace
+            | RE::UnicodeProperty(..) => {
+                // A run of single characters can only match one way, so it is stepped over in a
+                // loop rather than with a level of recursion for each character
+                let (run, text) = self.single_char_run(pattern);
+                if run > 1 {
+                    let mut local_context = self.clone();
+                    local_context.text = text;
+                    if local_context.match_here(&pattern[run..], next) {
+                        *self = local_context;
+                        return true;
+                    }
+                    return false;
+                }
+                let first = self.text.chars().next();
+                if let Some(first) = first.filter(|&first| self.matches_char(&pattern[0], first)) {
+                    let mut local_context = self.clone();
+                    local_context.text = &self.text[first.len_utf8()..];
+                    if local_context.match_here(&pattern[1..], next) {
+                        *self = local_context; // Update self with successful match state
+                        return true;
+                    }
+                }
+                self.flags.case_insensitive && self.match_multi_fold(pattern, next)
+            }
+            RE::Repeat { inner, min, max, greediness } => {
+                self.match_quantified(inner, *min, *max, *greediness, &pattern[1..], next)
+            }
+            RE::WordBoundary => self.at_word_boundary() && self.match_here(&pattern[1..], next),
+            RE::NonWordBoundary => !self.at_word_boundary() && self.match_here(&pattern[1..], next),
+            RE::AbsoluteStart => self.offset() == 0 && self.match_here(&pattern[1..], next),
+            RE::AbsoluteEnd => self.offset() == self.input.len() && self.match_here(&pattern[1..], next),
+            RE::ContinuationAnchor => self.offset() == self.last_match_end && self.match_here(&pattern[1..], next),
+            RE::AbsoluteEndOrNewline => {
+                let rest = &self.input[self.offset()..];
+                (rest.is_empty() || rest == "\n") && self.match_here(&pattern[1..], next)
+            }
+            RE::KeepStart => {
+                let mut local_context = self.clone();
+                local_context.kept_start = Some(self.offset());
+                if local_context.match_here(&pattern[1..], next) {
+                    *self = local_context;
+                    return true;
+                }
+                false
+            }
+            RE::SetFlags(flags) => {
+                let mut local_context = self.clone();
+                local_context.flags = *flags;
+                if local_context.match_here(&pattern[1..], next) {
+                    *self = local_context;
+                    return true;
+                }
+                false
+            }
+            RE::Backreference(group_index) => {
+                if let Some(captured) = self.captures.get(group_index) {
+                    if let Some(len) = self.captured_len(captured) {
+                        let mut local_context = self.clone();
+                        local_context.text = &self.text[len..];
+                        if local_context.match_here(&pattern[1..], next) {
+                            *self = local_context;
+                            return true;
+                        }
+                    }
+                }
+                false
+            }
+            RE::NamedBackreference(name) => {
+                if let Some(captured) = self.named_captures.get(name) {
+                    if let Some(len) = self.captured_len(captured) {
+                        let mut local_context = self.clone();
+                        local_context.text = &self.text[len..];
+                        if local_context.match_here(&pattern[1..], next) {
+                            *self = local_context;
+                            return true;
+                        }
+                    }
+                }
+                false
+            }
+            RE::Group(index, group_pattern) => self.match_group(*index, None, group_pattern, &pattern[1..], next),
+            RE::NamedGroup(index, name, group_pattern) => {
+                self.match_group(*index, Some(name), group_pattern, &pattern[1..], next)
+            }
+            RE::NonCapturingGroup(group_pattern) => {
+                // Same as RE::Group, but nothing is captured and no group index is used up
+                let mut local_context = self.clone();
+                if local_context.match_here(group_pattern, &Continuation::Then(&pattern[1..], next)) {
+                    *self = local_context;
+                    return true;
+                }
+                false
+            }
+            RE::AtomicGroup(group_pattern) => {
+                // Matching stops at the inner pattern's first success, so its end position is
+                // never revisited when the rest of the pattern fails
+                let mut local_context = self.clone();
+                if local_context.match_here(group_pattern, &Continuation::Done) && local_context.match_here(&pattern[1..], next) {
+                    *self = local_context;
+                    return true;
+                }
+                false
+            }
+            RE::LookaheadPos(group_pattern) => {
+                // Keep any captures made by the lookahead, but continue from where it started. A \K
+                // inside it does not move the start of the match.
+                let mut local_context = self.clone();
+                if local_context.match_here(group_pattern, &Continuation::Done) {
+                    local_context.text = self.text;
+                    local_context.kept_start = self.kept_start;
+                    if local_context.match_here(&pattern[1..], next) {
+                        *self = local_context;
+                        return true;
+                    }
+                }
+                false
+            }
+            RE::LookaheadNeg(group_pattern) => {
+                // Attempted on a throwaway context so no captures leak out of the assertion
+                !self.clone().match_here(group_pattern, &Continuation::Done) && self.match_here(&pattern[1..], next)
+            }
+            RE::LookbehindPos(group_pattern) => {
+                // Keep any captures made by the lookbehind, but continue from where it started. A \K
+                // inside it does not move the start of the match.
+                let mut local_context = self.clone();
+                if local_context.match_behind(group_pattern) {
+                    local_context.text = self.text;
+                    local_context.kept_start = self.kept_start;
+                    if local_context.match_here(&pattern[1..], next) {
+                        *self = local_context;
+                        return true;
+                    }
+                }
+                false
+            }
+            RE::LookbehindNeg(group_pattern) => {
+                // Attempted on a throwaway context so no captures leak out of the assertion
+                !self.clone().match_behind(group_pattern) && self.match_here(&pattern[1..], next)
+            }
+            RE::Conditional { group, yes, no } => {
+                let branch = if self.captures.contains_key(group) { yes } else { no };
+                self.match_here(branch, &Continuation::Then(&pattern[1..], next))
+            }
+            RE::Alternation(alternatives) => {
+                let after = Continuation::Then(&pattern[1..], next);
+                for alternative in alternatives {
+                    let mut local_context = self.clone();
+                    if local_context.match_here(alternative, &after) {
+                        *self = local_context;
+                        return true;
+                    }
+                }
+                false
+            }
+        }
+    }
+
+    // Matches a capturing group followed by `rest`, recording what the group matched under its
+    // index and, for named groups, under its name as well.
+    fn match_group(
+        &mut self,
+        index: usize,
+        name: Option<&str>,
+        group_pattern: &[RE],
+        rest: &[RE],
+        next: &Continuation,
+    ) -> bool {
+        let mut local_context = self.clone();
+
+        // The capture is recorded once the body has matched, right before the rest of the pattern
+        let after = Continuation::Then(rest, next);
+        let close = Continuation::CloseGroup {
+            index,
+            name,
+            start: self.offset(),
+            next: &after,
+        };
+        if local_context.match_here(group_pattern, &close) {
+            *self = local_context; // Update self with successful match state
+            return true;
+        }
+        false
+    }
+
+    // Matches the fixed-length `pattern` so that it ends exactly at the current position
+    fn match_behind(&mut self, pattern: &[RE]) -> bool {
+        let offset = self.offset();
+        let length = fixed_length(pattern).expect("Lookbehind assertions must have a fixed length");
+        let start = if length == 0 {
+            Some(offset)
+        } else {
+            self.input[..offset].char_indices().rev().nth(length - 1).map(|(idx, _)| idx)
+        };
+
+        match start {
+            Some(start) => {
+                self.text = &self.input[start..];
+                self.match_here(pattern, &Continuation::Done)
+            }
+            // Not enough input before the current position
+            None => false,
+        }
+    }
+
+    // Byte offset of the current position within the whole input
+    fn offset(&self) -> usize {
+        self.text.as_ptr() as usize - self.input.as_ptr() as usize
+    }
+
+    // The character just before the current position, if any
+    fn prev_char(&self) -> Option<char> {
+        self.input[..self.offset()].chars().next_back()
+    }
+
+    fn at_word_boundary(&self) -> bool {
+        let is_word = |c: Option<char>| c.is_some_and(|c| is_word_char(c, self.flags));
+        is_word(self.prev_char()) != is_word(self.text.chars().next())
+    }
+
+    // Matches `re` between `min` and `max` times followed by `rest`, trying repetition counts
+    // in the order given by `greediness`.
+    fn match_quantified(
+        &mut self,
+        re: &RE,
+        min: usize,
+        max: Option<usize>,
+        greediness: Greediness,
+        rest: &[RE],
+        next: &Continuation,
+    ) -> bool {
+        // Sub-patterns that may span several characters, such as groups, go the general way
+        if !is_single_char(re) {
+            if greediness == Greediness::Possessive {
+                return self.match_possessive(re, min, max, rest, next);
+            }
+            return self.match_repetitions(re, min, max, greediness, 0, &Continuation::Then(rest, next));
+        }
+
+        let mut local_context = self.clone();
+        for _ in 0..min {
+            if !local_context.consume_char(re) {
+                return false;
+            }
+        }
+
+        let mut count = min;
+        if greediness == Greediness::Lazy {
+            loop {
+                let mut attempt = local_context.clone();
+                if attempt.match_here(rest, next) {
+                    *self = attempt;
+                    return true;
+                }
+                if max.is_some_and(|max| count >= max) || !local_context.consume_char(re) {
+                    return false;
+                }
+                count += 1;
+            }
+        }
+
+        // Consume greedily up to the maximum, remembering each stopping point to backtrack through
+        let mut positions = vec![local_context.text];
+        while max.map_or(true, |max| count < max) && local_context.consume_char(re) {
+            positions.push(local_context.text);
+            count += 1;
+        }
+
+        if greediness == Greediness::Possessive {
+            // Only the longest run is ever tried
+            positions.drain(..positions.len() - 1);
+        }
+
+        for text in positions.into_iter().rev() {
+            let mut attempt = local_context.clone();
+            attempt.text = text;
+            if attempt.match_here(rest, next) {
+                *self = attempt;
+                return true;
+            }
+        }
+        false
+    }
+
+    // Matches further repetitions of `re`, `count` of which have already matched, followed by
+    // `next`. Each repetition is matched with a continuation back into here, so a repetition
+    // can backtrack into a shorter match when the ones after it fail.
+    fn match_repetitions(
+        &mut self,
+        re: &RE,
+        min: usize,
+        max: Option<usize>,
+        greediness: Greediness,
+        count: usize,
+        next: &Continuation,
+    ) -> bool {
+        if count < min {
+            return self.match_one_more(re, min, max, greediness, count, next);
+        }
+        match greediness {
+            Greediness::Lazy => {
+                self.match_here(&[], next) || self.match_one_more(re, min, max, greediness, count, next)
+            }
+            _ => self.match_one_more(re, min, max, greediness, count, next) || self.match_here(&[], next),
+        }
+    }
+
+    fn match_one_more(
+        &mut self,
+        re: &RE,
+        min: usize,
+        max: Option<usize>,
+        greediness: Greediness,
+        count: usize,
+        next: &Continuation,
+    ) -> bool {
+        if max.is_some_and(|max| count >= max) {
+            return false;
+        }
+        let repeat = Continuation::Repeat {
+            re,
+            min,
+            max,
+            greediness,
+            count: count + 1,
+            start: self.offset(),
+            next,
+        };
+        let mut local_context = self.clone();
+        if local_context.match_here(core::slice::from_ref(re), &repeat) {
+            *self = local_context;
+            return true;
+        }
+        false
+    }
+
+    // Matches as many repetitions of `re` as possible, each taking its first match like an
+    // atomic group, and never gives any of them back.
+    fn match_possessive(&mut self, re: &RE, min: usize, max: Option<usize>, rest: &[RE], next: &Continuation) -> bool {
+        let mut local_context = self.clone();
+        let mut count = 0;
+        while max.map_or(true, |max| count < max) {
+            let mut attempt = local_context.clone();
+            if !attempt.match_here(core::slice::from_ref(re), &Continuation::Done) {
+                break;
+            }
+            let advanced = attempt.offset() != local_context.offset();
+            local_context = attempt;
+            count += 1;
+            if !advanced && count >= min {
+                break;
+            }
+        }
+        if count >= min && local_context.match_here(rest, next) {
+            *self = local_context;
+            return true;
+        }
+        false
+    }
+
+    // Advances past the next character if it matches `re`.
+    fn consume_char(&mut self, re: &RE) -> bool {
+        match self.text.chars().next() {
+            Some(c) if self.matches_char(re, c) => {
+                self.text = &self.text[c.len_utf8()..];
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Case-insensitive matches where one character stands for several, as 'ß' does for "ss":
+    // either a literal in the pattern against several input characters, or a run of literals in
+    // the pattern against one input character.
+    fn match_multi_fold(&mut self, pattern: &[RE], next: &Continuation) -> bool {
+        let Some(RE::Char(c)) = pattern.first() else {
+            return false;
+        };
+        if let Some(len) = full_fold(*c).and_then(|expansion| self.folded_prefix_len(expansion)) {
+            let mut local_context = self.clone();
+            local_context.text = &self.text[len..];
+            if local_context.match_here(&pattern[1..], next) {
+                *self = local_context;
+                return true;
+            }
+        }
+        let first = self.text.chars().next();
+        if let Some((first, expansion)) = first.and_then(|first| Some((first, full_fold(first)?))) {
+            let count = expansion.chars().count();
+            let literals = pattern.iter().take(count).map(|re| match re {
+                RE::Char(c) => Some(fold_case(*c)),
+                _ => None,
+            });
+            if literals.eq(expansion.chars().map(Some)) {
+                let mut local_context = self.clone();
+                local_context.text = &self.text[first.len_utf8()..];
+                if local_context.match_here(&pattern[count..], next) {
+                    *self = local_context;
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    // How many of the single-character patterns at the start of `pattern` match the text one after
+    // another, and the text after them. The run stops short of any character that could also
+    // match through a multi-character case fold, since those can match in more than one way.
+    fn single_char_run(&self, pattern: &[RE]) -> (usize, &'a str) {
+        let mut text = self.text;
+        let mut run = 0;
+        for re in pattern.iter().take_while(|re| is_single_char(re)) {
+            let Some(c) = text.chars().next().filter(|&c| self.matches_char(re, c)) else {
+                break;
+            };
+            let folds = matches!(re, RE::Char(p) if full_fold(*p).is_some()) || full_fold(c).is_some();
+            if self.flags.case_insensitive && folds {
+                break;
+            }
+            text = &text[c.len_utf8()..];
+            run += 1;
+        }
+        (run, text)
+    }
+
+    // The byte length of the input characters at the current position that fold to `folded`
+    fn folded_prefix_len(&self, folded: &str) -> Option<usize> {
+        let mut len = 0;
+        let mut text = self.text.chars();
+        for expected in folded.chars() {
+            let c = text.next().filter(|&c| fold_case(c) == expected)?;
+            len += c.len_utf8();
+        }
+        Some(len)
+    }
+
+    fn chars_equal(&self, pattern_char: char, c: char) -> bool {
+        pattern_char == c || (self.flags.case_insensitive && fold_case(pattern_char) == fold_case(c))
+    }
+
+    // The byte length of the text at the current position that matches `captured`, which can
+    // differ from the captured text's own length when case is ignored, as with 'K' and the
+    // Kelvin sign
+    fn captured_len(&self, captured: &str) -> Option<usize> {
+        if !self.flags.case_insensitive {
+            return self.text.starts_with(captured).then_some(captured.len());
+        }
+        let mut len = 0;
+        let mut text = self.text.chars();
+        for expected in captured.chars() {
+            let c = text.next().filter(|&c| self.chars_equal(expected, c))?;
+            len += c.len_utf8();
+        }
+        Some(len)
+    }
+
+    fn class_contains(&self, class: &CharClass, c: char) -> bool {
+        if self.flags.case_insensitive {
+            let (lower, upper) = (single_char(c.to_lowercase()), single_char(c.to_uppercase()));
+            class.contains(c, self.flags)
+                || lower.is_some_and(|lower| class.contains(lower, self.flags))
+                || upper.is_some_and(|upper| class.contains(upper, self.flags))
+        } else {
+            class.contains(c, self.flags)
+        }
+    }
+
+    fn matches_char(&self, re: &RE, c: char) -> bool {
+        match re {
+            RE::Char(ch) => self.chars_equal(*ch, c),
+            RE::Dot => self.flags.dotall || c != '\n',
+            RE::Digit
+            | RE::Word
+            | RE::NonDigit
+            | RE::NonWord
+            | RE::Space
+            | RE::NonSpace
+            | RE::UnicodeProperty(..) => matches_shorthand(re, c, self.flags),
+            RE::CharClass(class) => self.class_contains(class, c),
+            RE::NegCharClass(class) => !self.class_contains(class, c),
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for RegexEngine {
+    type Err = Error;
+
+    fn from_str(pattern: &str) -> Result<Self, Error> {
+        RegexEngine::new(pattern)
+    }
+}
+
+// Writes the parsed pattern back out as pattern syntax. Flags set with RegexBuilder are not part
+// of the output.
+impl fmt::Display for RegexEngine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_sequence(f, &self.pattern)
+    }
+}
+
+// A single match of a pattern, with byte offsets into the text that was searched
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Match<'t> {
+    text: &'t str,
+    start: usize,
+    end: usize,
+}
+
+impl<'t> Match<'t> {
+    // The matched part of the text
+    pub fn as_str(&self) -> &'t str {
+        &self.text[self.start..self.end]
+    }
+
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    // Where to look for the next match so that matches never overlap, or None at the end of the
+    // text. An empty match would be found again at the same place, so it steps over one character.
+    fn resume_at(&self) -> Option<usize> {
+        if self.end > self.start {
+            Some(self.end)
+        } else {
+            self.text[self.end..].chars().next().map(|c| self.end + c.len_utf8())
+        }
+    }
+}
+
+// The groups captured by a single match. Group 0 is the whole match.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Captures<'t> {
+    whole: Match<'t>,
+    groups: BTreeMap<usize, String>,
+    named_groups: BTreeMap<String, String>,
+}
+
+impl<'t> Captures<'t> {
+    // The text captured by group `index`, or None if that group did not take part in the match
+    pub fn get(&self, index: usize) -> Option<&str> {
+        if index == 0 {
+            Some(self.whole.as_str())
+        } else {
+            self.groups.get(&index).map(String::as_str)
+        }
+    }
+
+    // The text captured by the group named `name`
+    pub fn name(&self, name: &str) -> Option<&str> {
+        self.named_groups.get(name).map(String::as_str)
+    }
+
+    // The whole match
+    pub fn whole(&self) -> Match<'t> {
+        self.whole
+    }
+}
+
+impl Index<usize> for Captures<'_> {
+    type Output = str;
+
+    fn index(&self, index: usize) -> &str {
+        self.get(index).unwrap_or_else(|| panic!("no group at index {}", index))
+    }
+}
+
+// A compiled pattern. Searching never changes the engine: the state of a search lives in a
+// MatchContext made for that search alone, so one engine can be shared between threads.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RegexEngine {
+    pattern_str: String, // The pattern as it was written
+    pattern: Vec<RE>,
+    flags: Flags,
+    backtrack_limit: usize,
+    depth_limit: usize,
+    memoize: bool, // Whether searches remember which states failed
+    #[cfg_attr(feature = "serde", serde(default))]
+    binary_mode: bool, // Whether byte slices are searched one byte per character
+}
+
+impl RegexEngine {
+    // Compiles `pattern` with no flags set; use RegexBuilder to set them up front
+    pub fn new(pattern: &str) -> Result<Self, Error> {
+        RegexBuilder::new(pattern).build()
+    }
+
+    pub fn match_text(&self, text: &str) -> bool {
+        self.find(text).is_some()
+    }
+
+    // Like match_text, but reuses the memory in `cache` from earlier searches instead of
+    // allocating it afresh, which adds up when matching many short strings in a loop
+    pub fn match_text_reuse(&self, text: &str, cache: &mut MatchCache) -> bool {
+        self.search_with(&self.pattern, text, 0, 0, cache).ok().flatten().is_some()
+    }
+
+    // Escapes `text` so that, used as a pattern or part of one, it matches itself literally. This
+    // holds in free-spacing mode too, so whitespace and '#' are escaped along with metacharacters.
+    pub fn escape(text: &str) -> String {
+        let mut escaped = String::with_capacity(text.len());
+        for c in text.chars() {
+            match c {
+                '.' | '*' | '+' | '?' | '^' | '$' | '{' | '}' | '[' | ']' | '(' | ')' | '|' | '\\' | '#' | ' ' => {
+                    escaped.push('\\');
+                    escaped.push(c);
+                }
+                '\t' => escaped.push_str("\\t"),
+                '\n' => escaped.push_str("\\n"),
+                '\r' => escaped.push_str("\\r"),
+                c if c.is_whitespace() => escaped.push_str(&format!("\\u{:04X}", c as u32)),
+                c => escaped.push(c),
+            }
+        }
+        escaped
+    }
+
+    // The pattern this engine was built from
+    pub fn as_str(&self) -> &str {
+        &self.pattern_str
+    }
+
+    // The parsed pattern as a Graphviz graph, with a node for each part of the pattern and edges
+    // to the parts inside it, numbered in matching order. Render it with `dot -Tsvg`.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph pattern {\n    node [shape=box];\n");
+        dot_sequence(&mut dot, &mut 0, "Pattern", &self.pattern);
+        dot.push_str("}\n");
+        dot
+    }
+
+    // How many capturing groups the pattern has, not counting the whole match as group 0
+    pub fn captures_len(&self) -> usize {
+        let highest = Cell::new(0);
+        find_node(&self.pattern, &|re| {
+            if let RE::Group(index, _) | RE::NamedGroup(index, _, _) = re {
+                highest.set(highest.get().max(*index));
+            }
+            false
+        });
+        highest.get()
+    }
+
+    // The flags the pattern was built with, not counting inline flags inside it
+    pub fn flags(&self) -> Flags {
+        self.flags
+    }
+
+    // Finds the leftmost match in `text`. A search that runs past the backtracking limit finds
+    // nothing; use try_find to tell that apart from there being no match.
+    pub fn find<'t>(&self, text: &'t str) -> Option<Match<'t>> {
+        self.find_at(text, 0, 0)
+    }
+
+    // Finds the leftmost match that starts at or after byte offset `start_byte`. Anchors,
+    // lookbehinds and \b still see the text before it, so this is not the same as searching
+    // `&text[start_byte..]`. Offsets in the match are relative to all of `text`. Panics if
+    // `start_byte` is not on a character boundary.
+    pub fn match_text_at<'t>(&self, text: &'t str, start_byte: usize) -> Option<Match<'t>> {
+        assert!(text.is_char_boundary(start_byte), "match_text_at: {} is not a character boundary", start_byte);
+        self.find_at(text, start_byte, start_byte)
+    }
+
+    // Like find, but fails with Error::BacktrackLimitExceeded if the search was cut short
+    pub fn try_find<'t>(&self, text: &'t str) -> Result<Option<Match<'t>>, Error> {
+        Ok(self.try_captures_at(text, 0, 0)?.map(|caps| caps.whole))
+    }
+
+    // Iterates over all non-overlapping matches in `text`, from left to right
+    pub fn find_iter<'t>(&self, text: &'t str) -> Matches<'_, 't> {
+        Matches {
+            engine: self,
+            text,
+            position: Some(0),
+            last_end: 0,
+        }
+    }
+
+    // All non-overlapping matches in `text`, from left to right. An empty match is followed by a
+    // search one character further on, so `a*` finds an empty match at every position in "bbb".
+    pub fn match_all<'t>(&self, text: &'t str) -> Vec<Match<'t>> {
+        self.find_iter(text).collect()
+    }
+
+    // Like match_all, but each search starts one character after where the previous match
+    // started rather than where it ended, so matches may overlap: "aa" finds "aaa" twice.
+    pub fn match_all_overlapping<'t>(&self, text: &'t str) -> Vec<Match<'t>> {
+        let mut matches = Vec::new();
+        let mut position = Some(0);
+        let mut last_end = 0;
+        while let Some(m) = position.and_then(|position| self.find_at(text, position, last_end)) {
+            position = text[m.start..].chars().next().map(|c| m.start + c.len_utf8());
+            last_end = m.end;
+            matches.push(m);
+        }
+        matches
+    }
+
+    // Whether the pattern matches all of `text`, as if it were wrapped in \A(?:...)\z
+    pub fn is_full_match(&self, text: &str) -> bool {
+        self.full_match(text).is_some()
+    }
+
+    // The match of the pattern against all of `text`, if there is one. Unlike with ^ and $, a
+    // trailing newline or the multiline flag cannot make a match end early.
+    pub fn full_match<'t>(&self, text: &'t str) -> Option<Match<'t>> {
+        let anchored = [RE::AbsoluteStart, RE::NonCapturingGroup(self.pattern.clone()), RE::AbsoluteEnd];
+        self.search(&anchored, text, 0, 0).ok().flatten().map(|caps| caps.whole)
+    }
+
+    // The match of the pattern at the very start of `text`, if there is one, as if the pattern
+    // were wrapped in \A(?:...)
+    pub fn starts_with<'t>(&self, text: &'t str) -> Option<Match<'t>> {
+        let anchored = [RE::AbsoluteStart, RE::NonCapturingGroup(self.pattern.clone())];
+        self.search(&anchored, text, 0, 0).ok().flatten().map(|caps| caps.whole)
+    }
+
+    // The leftmost match of the pattern that ends at the very end of `text`, if there is one, as
+    // if the pattern were wrapped in (?:...)\z
+    pub fn ends_with<'t>(&self, text: &'t str) -> Option<Match<'t>> {
+        let anchored = [RE::NonCapturingGroup(self.pattern.clone()), RE::AbsoluteEnd];
+        self.search(&anchored, text, 0, 0).ok().flatten().map(|caps| caps.whole)
+    }
+
+    // Finds the leftmost match in `text` along with what each group captured
+    pub fn captures<'t>(&self, text: &'t str) -> Option<Captures<'t>> {
+        self.captures_at(text, 0, 0)
+    }
+
+    // Like captures, but fails with Error::BacktrackLimitExceeded if the search was cut short
+    pub fn try_captures<'t>(&self, text: &'t str) -> Result<Option<Captures<'t>>, Error> {
+        self.try_captures_at(text, 0, 0)
+    }
+
+    // Like find_iter, but yields the captures of each match
+    pub fn captures_iter<'t>(&self, text: &'t str) -> CaptureMatches<'_, 't> {
+        CaptureMatches {
+            engine: self,
+            text,
+            position: Some(0),
+            last_end: 0,
+        }
+    }
+
+    // Iterates over the pieces of `text` between matches
+    pub fn split<'t>(&self, text: &'t str) -> SplitIter<'_, 't> {
+        SplitIter {
+            matches: self.find_iter(text),
+            text,
+            last_end: 0,
+            limit: None,
+            done: false,
+        }
+    }
+
+    // Like split, but yields at most `n` pieces; the last one holds the rest of the text unsplit
+    pub fn splitn<'t>(&self, text: &'t str, n: usize) -> SplitIter<'_, 't> {
+        SplitIter {
+            limit: Some(n),
+            ..self.split(text)
+        }
+    }
+
+    // Replaces the leftmost match with `rep`, in which $0 to $9 and ${name} stand for captures
+    pub fn replace(&self, text: &str, rep: &str) -> String {
+        self.replace_n(text, Some(1), |caps| expand_replacement(rep, caps))
+    }
+
+    // Replaces every non-overlapping match with `rep`, expanded as in replace
+    pub fn replace_all(&self, text: &str, rep: &str) -> String {
+        self.replace_n(text, None, |caps| expand_replacement(rep, caps))
+    }
+
+    // Replaces the leftmost match with whatever `rep` returns for it
+    pub fn replace_with<F: Fn(&Captures) -> String>(&self, text: &str, rep: F) -> String {
+        self.replace_n(text, Some(1), rep)
+    }
+
+    // Replaces every non-overlapping match with whatever `rep` returns for it
+    pub fn replace_all_with<F: Fn(&Captures) -> String>(&self, text: &str, rep: F) -> String {
+        self.replace_n(text, None, rep)
+    }
+
+    // Whether the pattern matches somewhere in `input`. Unless the engine was built in binary
+    // mode, `input` has to be valid UTF-8 and nothing matches if it is not.
+    pub fn match_bytes(&self, input: &[u8]) -> bool {
+        self.find_bytes(input).is_some()
+    }
+
+    // Finds the leftmost match in `input`, with byte offsets into it. In binary mode each byte
+    // is one character, the one with the same value, so \xFF matches the byte 0xFF and . matches
+    // any byte, 0x00 included, but '\n'.
+    pub fn find_bytes<'t>(&self, input: &'t [u8]) -> Option<BytesMatch<'t>> {
+        self.find_iter_bytes(input).next()
+    }
+
+    // Iterates over all non-overlapping matches in `input`, from left to right
+    pub fn find_iter_bytes<'t>(&self, input: &'t [u8]) -> BytesMatches<'_, 't> {
+        let decoded = ByteText::decode(input, self.binary_mode);
+        BytesMatches {
+            engine: self,
+            input,
+            position: decoded.as_ref().map(|_| 0),
+            decoded,
+            last_end: 0,
+        }
+    }
+
+    // Replaces up to `limit` matches, or all of them if there is no limit
+    fn replace_n<F: Fn(&Captures) -> String>(&self, text: &str, limit: Option<usize>, rep: F) -> String {
+        let mut result = String::with_capacity(text.len());
+        let mut last_end = 0;
+        for caps in self.captures_iter(text).take(limit.unwrap_or(usize::MAX)) {
+            result.push_str(&text[last_end..caps.whole.start]);
+            result.push_str(&rep(&caps));
+            last_end = caps.whole.end;
+        }
+        result.push_str(&text[last_end..]);
+        result
+    }
+
+    // Finds the leftmost match that starts at or after byte offset `start`. Anchors and
+    // lookbehinds still see the text before `start`, and \G matches at `last_match_end`.
+    fn find_at<'t>(&self, text: &'t str, start: usize, last_match_end: usize) -> Option<Match<'t>> {
+        self.captures_at(text, start, last_match_end).map(|caps| caps.whole)
+    }
+
+    fn captures_at<'t>(&self, text: &'t str, start: usize, last_match_end: usize) -> Option<Captures<'t>> {
+        self.try_captures_at(text, start, last_match_end).ok().flatten()
+    }
+
+    fn try_captures_at<'t>(&self, text: &'t str, start: usize, last_match_end: usize) -> Result<Option<Captures<'t>>, Error> {
+        self.search(&self.pattern, text, start, last_match_end)
+    }
+
+    // Runs `pattern`, which is this engine's pattern or one built around it, with its limits
+    fn search<'t>(&self, pattern: &[RE], text: &'t str, start: usize, last_match_end: usize) -> Result<Option<Captures<'t>>, Error> {
+        self.search_with(pattern, text, start, last_match_end, &mut MatchCache::new())
+    }
+
+    // Like search, but keeps the memory for remembered failures in `cache` for the next search
+    fn search_with<'t>(
+        &self,
+        pattern: &[RE],
+        text: &'t str,
+        start: usize,
+        last_match_end: usize,
+        cache: &mut MatchCache,
+    ) -> Result<Option<Captures<'t>>, Error> {
+        let mut failures = core::mem::take(&mut cache.failures);
+        failures.clear();
+        let search = SearchState {
+            steps_left: Cell::new(self.backtrack_limit),
+            steps_exceeded: Cell::new(false),
+            depth_limit: self.depth_limit,
+            depth_exceeded: Cell::new(false),
+            failures: self.memoize.then(|| RefCell::new(failures)),
+        };
+        let mut context = MatchContext::new(text, self.flags, &search);
+        context.text = &text[start..];
+        context.last_match_end = last_match_end;
+        let found = context.match_pattern(pattern);
+        // An attempt cut short by a limit may have been a negative lookaround or an alternative
+        // that would have matched first, so even a match found despite it cannot be trusted
+        let result = if search.depth_exceeded.get() {
+            Err(Error::StackDepthExceeded)
+        } else if search.steps_exceeded.get() {
+            Err(Error::BacktrackLimitExceeded)
+        } else {
+            Ok(found.map(|(start, end)| Captures {
+                whole: Match { text, start, end },
+                groups: context.captures,
+                named_groups: context.named_captures,
+            }))
+        };
+        if let Some(failures) = search.failures {
+            cache.failures = failures.into_inner();
+        }
+        result
+    }
+}
+
+// Expands a replacement string: $0 to $9 and ${index} insert numbered captures, ${name} a named
+// one and $$ a literal '$'. Groups that did not take part in the match insert nothing.
+fn expand_replacement(rep: &str, caps: &Captures) -> String {
+    let mut result = String::new();
+    let mut rest = rep;
+    while let Some(dollar) = rest.find('$') {
+        result.push_str(&rest[..dollar]);
+        rest = &rest[dollar + 1..];
+        match rest.chars().next() {
+            Some('$') => {
+                result.push('$');
+                rest = &rest[1..];
+            }
+            Some(digit) if digit.is_ascii_digit() => {
+                result.push_str(caps.get(digit as usize - '0' as usize).unwrap_or(""));
+                rest = &rest[1..];
+            }
+            Some('{') => match rest.find('}') {
+                Some(close) => {
+                    let name = &rest[1..close];
+                    let captured = match name.parse::<usize>() {
+                        Ok(index) => caps.get(index),
+                        Err(_) => caps.name(name),
+                    };
+                    result.push_str(captured.unwrap_or(""));
+                    rest = &rest[close + 1..];
+                }
+                // No closing brace, so the '$' is literal
+                None => result.push('$'),
+            },
+            _ => result.push('$'),
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+// Iterator over the matches of a pattern in a text, created by RegexEngine::find_iter
+pub struct Matches<'r, 't> {
+    engine: &'r RegexEngine,
+    text: &'t str,
+    position: Option<usize>, // Where the next search starts, or None once the text is used up
+    last_end: usize,         // Where the previous match ended
+}
+
+impl<'t> Iterator for Matches<'_, 't> {
+    type Item = Match<'t>;
+
+    fn next(&mut self) -> Option<Match<'t>> {
+        let m = self.engine.find_at(self.text, self.position?, self.last_end)?;
+        self.position = m.resume_at();
+        self.last_end = m.end;
+        Some(m)
+    }
+}
+
+// A match in a byte slice, created by RegexEngine::find_bytes and find_iter_bytes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BytesMatch<'t> {
+    input: &'t [u8],
+    start: usize,
+    end: usize,
+}
+
+impl<'t> BytesMatch<'t> {
+    // The matched bytes
+    pub fn as_bytes(&self) -> &'t [u8] {
+        &self.input[self.start..self.end]
+    }
+
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn end(&self) -> usize {
+        self.end
+    }
+}
+
+// A byte slice as text the engine can search. In binary mode each byte becomes the char with the
+// same value, which takes two bytes of UTF-8 from 0x80 up, so `offsets` maps each byte of the
+// text back to the byte of the input it came from.
+struct ByteText<'t> {
+    text: Cow<'t, str>,
+    offsets: Option<Vec<usize>>,
+}
+
+impl<'t> ByteText<'t> {
+    // None if `input` is not valid UTF-8 outside binary mode
+    fn decode(input: &'t [u8], binary_mode: bool) -> Option<Self> {
+        if !binary_mode {
+            let text = core::str::from_utf8(input).ok()?;
+            return Some(ByteText { text: Cow::Borrowed(text), offsets: None });
+        }
+        let mut text = String::with_capacity(input.len());
+        let mut offsets = Vec::with_capacity(input.len() + 1);
+        for (i, &b) in input.iter().enumerate() {
+            let c = char::from(b);
+            text.push(c);
+            offsets.extend(core::iter::repeat(i).take(c.len_utf8()));
+        }
+        offsets.push(input.len());
+        Some(ByteText { text: Cow::Owned(text), offsets: Some(offsets) })
+    }
+
+    // The offset in the input of byte `index` of the text
+    fn input_offset(&self, index: usize) -> usize {
+        self.offsets.as_ref().map_or(index, |offsets| offsets[index])
+    }
+}
+
+// Iterator over the matches of a pattern in a byte slice, created by RegexEngine::find_iter_bytes
+pub struct BytesMatches<'r, 't> {
+    engine: &'r RegexEngine,
+    input: &'t [u8],
+    decoded: Option<ByteText<'t>>, // None if the input could not be searched at all
+    position: Option<usize>,       // Where the next search starts in the decoded text
+    last_end: usize,
+}
+
+impl<'t> Iterator for BytesMatches<'_, 't> {
+    type Item = BytesMatch<'t>;
+
+    fn next(&mut self) -> Option<BytesMatch<'t>> {
+        let decoded = self.decoded.as_ref()?;
+        let m = self.engine.find_at(&decoded.text, self.position?, self.last_end)?;
+        self.position = m.resume_at();
+        self.last_end = m.end;
+        Some(BytesMatch {
+            input: self.input,
+            start: decoded.input_offset(m.start),
+            end: decoded.input_offset(m.end),
+        })
+    }
+}
+
+// Iterator over the pieces of a text between matches, created by RegexEngine::split and splitn
+pub struct SplitIter<'r, 't> {
+    matches: Matches<'r, 't>,
+    text: &'t str,
+    last_end: usize,      // Where the next piece starts
+    limit: Option<usize>, // How many more pieces splitn may yield
+    done: bool,
+}
+
+impl<'t> Iterator for SplitIter<'_, 't> {
+    type Item = &'t str;
+
+    fn next(&mut self) -> Option<&'t str> {
+        if self.done {
+            return None;
+        }
+        if let Some(limit) = &mut self.limit {
+            if *limit <= 1 {
+                // The last allowed piece takes everything that is left
+                self.done = true;
+                return (*limit == 1).then(|| &self.text[self.last_end..]);
+            }
+            *limit -= 1;
+        }
+        match self.matches.next() {
+            Some(m) => {
+                let piece = &self.text[self.last_end..m.start];
+                self.last_end = m.end;
+                Some(piece)
+            }
+            None => {
+                self.done = true;
+                Some(&self.text[self.last_end..])
+            }
+        }
+    }
+}
+
+// Iterator over the captures of each match, created by RegexEngine::captures_iter
+pub struct CaptureMatches<'r, 't> {
+    engine: &'r RegexEngine,
+    text: &'t str,
+    position: Option<usize>,
+    last_end: usize,
+}
+
+impl<'t> Iterator for CaptureMatches<'_, 't> {
+    type Item = Captures<'t>;
+
+    fn next(&mut self) -> Option<Captures<'t>> {
+        let caps = self.engine.captures_at(self.text, self.position?, self.last_end)?;
+        self.position = caps.whole.resume_at();
+        self.last_end = caps.whole.end;
+        Some(caps)
+    }
+}
+
+// Several patterns tested against the same text, for example every -e given to the CLI. The
+// patterns are still tried one after the other; what the set saves is compiling them once.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RegexSet {
+    engines: Vec<RegexEngine>,
+}
+
+impl RegexSet {
+    pub fn new(patterns: &[&str]) -> Result<RegexSet, Error> {
+        let engines = patterns.iter().map(|pattern| RegexEngine::new(pattern)).collect::<Result<_, _>>()?;
+        Ok(RegexSet { engines })
+    }
+
+    // Whether any of the patterns matches somewhere in `text`
+    pub fn is_match(&self, text: &str) -> bool {
+        self.engines.iter().any(|engine| engine.match_text(text))
+    }
+
+    // The indices of the patterns that match somewhere in `text`, in order
+    pub fn matches(&self, text: &str) -> Vec<usize> {
+        (0..self.engines.len()).filter(|&i| self.engines[i].match_text(text)).collect()
+    }
+
+    pub fn engines(&self) -> &[RegexEngine] {
+        &self.engines
+    }
+
+    pub fn len(&self) -> usize {
+        self.engines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.engines.is_empty()
+    }
+}
+
+// For engines built with their own flags, such as through RegexBuilder
+impl From<Vec<RegexEngine>> for RegexSet {
+    fn from(engines: Vec<RegexEngine>) -> Self {
+        RegexSet { engines }
+    }
+}
+
+// Enough for any reasonable pattern on a line of text, while still failing within a second
+const DEFAULT_BACKTRACK_LIMIT: usize = 1_000_000;
+// Deep enough for a group repeated a couple of hundred times, shallow enough for the 2 MB stack of
+// a spawned thread in a release build. Runs of literal characters take no depth at all.
+const DEFAULT_DEPTH_LIMIT: usize = 1_000;
+
+pub struct RegexBuilder {
+    pattern: String,
+    flags: Flags,
+    whole_words: bool,
+    backtrack_limit: usize,
+    depth_limit: usize,
+    memoize: bool,
+    binary_mode: bool,
+}
+
+impl RegexBuilder {
+    pub fn new(pattern: &str) -> Self {
+        Self {
+            pattern: pattern.to_string(),
+            flags: Flags::default(),
+            whole_words: false,
+            backtrack_limit: DEFAULT_BACKTRACK_LIMIT,
+            depth_limit: DEFAULT_DEPTH_LIMIT,
+            memoize: true,
+            binary_mode: false,
+        }
+    }
+
+    // Replaces all flags at once
+    pub fn flags(&mut self, flags: Flags) -> &mut Self {
+        self.flags = flags;
+        self
+    }
+
+    // Same as starting the pattern with (?i)
+    pub fn case_insensitive(&mut self, yes: bool) -> &mut Self {
+        self.flags.case_insensitive = yes;
+        self
+    }
+
+    // Same as starting the pattern with (?m)
+    pub fn multiline(&mut self, yes: bool) -> &mut Self {
+        self.flags.multiline = yes;
+        self
+    }
+
+    // Same as starting the pattern with (?s)
+    pub fn dot_matches_newline(&mut self, yes: bool) -> &mut Self {
+        self.flags.dotall = yes;
+        self
+    }
+
+    // Same as starting the pattern with (?x)
+    pub fn free_spacing(&mut self, yes: bool) -> &mut Self {
+        self.flags.free_spacing = yes;
+        self
+    }
+
+    // Whether the shorthand classes go by Unicode, which is the default, or only by ASCII. In
+    // Unicode mode \w and \b count any letter or digit, such as 'é' or '٣', and \s any
+    // whitespace, such as U+00A0. In ASCII mode \w is [a-zA-Z0-9_] and \s is [ \t\n\r\x0B\x0C].
+    // Either way \d is [0-9], and bracket expressions like [[:alpha:]] go by Unicode.
+    pub fn unicode(&mut self, yes: bool) -> &mut Self {
+        self.flags.unicode = yes;
+        self
+    }
+
+    // The old name of unicode, from when it only covered \w and \b; it now sets \s as well
+    #[deprecated(note = "use `unicode`, which also covers \\s")]
+    pub fn unicode_word(&mut self, yes: bool) -> &mut Self {
+        self.unicode(yes)
+    }
+
+    // Only accept matches that are not preceded or followed by a word character (\w), so that
+    // "cat" finds "a cat" but not "cats"
+    pub fn whole_words(&mut self, yes: bool) -> &mut Self {
+        self.whole_words = yes;
+        self
+    }
+
+    // How many steps a single search may take before giving up, so that patterns like (a+)+b
+    // cannot run for an exponential time
+    pub fn backtrack_limit(&mut self, limit: usize) -> &mut Self {
+        self.backtrack_limit = limit;
+        self
+    }
+
+    // How deeply a search may recurse, which is a few levels for each repetition of a group and
+    // each place the search may have to come back to. Runs of single characters, like literal
+    // text, and single-character quantifiers like .* and \d+ take none. Patterns that nest groups
+    // more deeply than this are rejected. Raising it is only safe on a thread with a large enough
+    // stack, about 1 KB per level in a release build.
+    pub fn depth_limit(&mut self, limit: usize) -> &mut Self {
+        self.depth_limit = limit;
+        self
+    }
+
+    // Remember which states of a search have failed, so that they are not tried again. This keeps
+    // patterns like (a+)+b polynomial, at some cost for simple ones. Patterns with backreferences
+    // are never memoized.
+    pub fn memoize(&mut self, yes: bool) -> &mut Self {
+        self.memoize = yes;
+        self
+    }
+
+    // Search byte slices one byte at a time, each byte standing for the char with the same value,
+    // instead of decoding them as UTF-8. Only affects match_bytes, find_bytes and find_iter_bytes.
+    pub fn binary_mode(&mut self, yes: bool) -> &mut Self {
+        self.binary_mode = yes;
+        self
+    }
+
+    pub fn build(&self) -> Result<RegexEngine, Error> {
+        // Parsing recurses into groups, so deep nesting has to be caught before it starts
+        if group_nesting(&self.pattern) > self.depth_limit {
+            return Err(Error::StackDepthExceeded);
+        }
+        let mut pattern = optimize(parse_pattern(&self.pattern, self.flags)?);
+        if self.whole_words {
+            // Same as (?<!\w)(?:pattern)(?!\w), without having to re-parse the pattern
+            pattern = vec![
+                RE::LookbehindNeg(vec![RE::Word]),
+                RE::NonCapturingGroup(pattern),
+                RE::LookaheadNeg(vec![RE::Word]),
+            ];
+        }
+        let memoize = self.memoize && !depends_on_captures(&pattern);
+        Ok(RegexEngine {
+            pattern_str: self.pattern.clone(),
+            pattern,
+            flags: self.flags,
+            backtrack_limit: self.backtrack_limit,
+            depth_limit: self.depth_limit,
+            memoize,
+            binary_mode: self.binary_mode,
+        })
+    }
+}
+
+// Checks that `pattern` is valid without building an engine for it
+pub fn validate_pattern(pattern: &str) -> Result<(), Error> {
+    if group_nesting(pattern) > DEFAULT_DEPTH_LIMIT {
+        return Err(Error::StackDepthExceeded);
+    }
+    parse_pattern(pattern, Flags::default()).map(|_| ())
+}
+
+pub(crate) fn parse_pattern(pattern: &str, mut flags: Flags) -> Result<Vec<RE>, Error> {
+    let mut result = Vec::new();
+    let mut group_count = 0; // Capturing groups are numbered by the position of their '('
+    let mut alternatives = Vec::new();
+    let initial_flags = flags;
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut i = 0;
+
+    while i < chars.len() {
+        if flags.free_spacing && skip_free_spacing(&chars, &mut i) {
+            continue;
+        }
+        match chars[i] {
+            '|' => {
+                // A top-level alternative; like in groups, the next one keeps any inline flags
+                alternatives.push(core::mem::take(&mut result));
+                if flags != initial_flags {
+                    result.push(RE::SetFlags(flags));
+                }
+            }
+            ')' => return Err(Error::UnmatchedParen),
+            '^' => result.push(RE::Start),
+            '$' => result.push(RE::End),
+            '.' => result.push(RE::Dot),
+            '\\' if chars.get(i + 1) == Some(&'Q') => parse_quoted(&chars, &mut i, &mut result),
+            '\\' => result.push(parse_escape(&chars, &mut i)?),
+            '[' => {
+                if i + 1 < chars.len() && chars[i + 1] == '^' {
+                    let (class, end_idx) = parse_char_class(&chars, i + 2)?;
+                    result.push(RE::NegCharClass(class));
+                    i = end_idx;
+                } else {
+                    let (class, end_idx) = parse_char_class(&chars, i + 1)?;
+                    result.push(RE::CharClass(class));
+                    i = end_idx;
+                }
+            }
+            '(' if chars[i..].starts_with(&['(', '?', '#']) => skip_comment(&chars, &mut i)?,
+            '(' => {
+                let (group, end_idx) = parse_alternation(&chars, i + 1, &mut flags, &mut group_count)?;
+                result.push(group);
+                i = end_idx;
+            }
+            quantifier @ ('?' | '+' | '*') => {
+                let inner = Box::new(result.pop().ok_or(Error::EmptyQuantifier(quantifier))?);
+                let (min, max) = match quantifier {
+                    '?' => (0, Some(1)),
+                    '+' => (1, None),
+                    _ => (0, None),
+                };
+                let greediness = parse_quantifier_suffix(&chars, &mut i);
+                result.push(RE::Repeat { inner, min, max, greediness });
+            }
+            '{' => match parse_repeat(&chars, i + 1) {
+                Some((min, Some(max), _)) if min > max => return Err(Error::InvalidRepeatCount(min, max)),
+                Some((min, max, end_idx)) => {
+                    i = end_idx;
+                    let inner = Box::new(result.pop().ok_or(Error::EmptyQuantifier('{'))?);
+                    let greediness = parse_quantifier_suffix(&chars, &mut i);
+                    result.push(RE::Repeat { inner, min, max, greediness });
+                }
+                // Not a valid counted quantifier, so treat the brace literally
+                None => result.push(RE::Char('{')),
+            },
+            ch => result.push(RE::Char(ch)),
+        }
+        i += 1;
+    }
+    if !alternatives.is_empty() {
+        alternatives.push(result);
+        result = vec![RE::Alternation(alternatives)];
+    }
+    // Backreferences may point forward, so they can only be checked once every group is known
+    if let Some(RE::Backreference(index)) = find_node(&result, &|re| matches!(re, RE::Backreference(index) if *index > group_count)) {
+        return Err(Error::InvalidBackreference(*index));
+    }
+    if let Some(RE::Conditional { group, .. }) = find_node(&result, &|re| matches!(re, RE::Conditional { group, .. } if *group > group_count)) {
+        return Err(Error::InvalidConditionGroup(*group));
+    }
+    // Group names are collected in order, so the first name to be seen twice is the one reported
+    let names = RefCell::new(BTreeSet::new());
+    if let Some(RE::NamedGroup(_, name, _)) = find_node(&result, &|re| matches!(re, RE::NamedGroup(_, name, _) if !names.borrow_mut().insert(name.clone()))) {
+        return Err(Error::DuplicateGroupName(name.clone()));
+    }
+    if let Some(RE::NamedBackreference(name)) = find_node(&result, &|re| matches!(re, RE::NamedBackreference(name) if !names.borrow().contains(name))) {
+        return Err(Error::InvalidNamedBackreference(name.clone()));
+    }
+    Ok(result)
+}
+
+pub(crate) fn parse_sequence(chars: &[char], i: &mut usize, flags: &mut Flags, group_count: &mut usize) -> Result<Vec<RE>, Error> {
+    let mut result = Vec::new();
+
+    while *i < chars.len() {
+        if flags.free_spacing && skip_free_spacing(chars, i) {
+            continue;
+        }
+        match chars[*i] {
+            '|' | ')' => break, // Stop when encountering alternation or end of group
+            '^' => result.push(RE::Start),
+            '$' => result.push(RE::End),
+            '.' => result.push(RE::Dot),
+            '\\' if chars.get(*i + 1) == Some(&'Q') => parse_quoted(chars, i, &mut result),
+            '\\' => result.push(parse_escape(chars, i)?),
+            '[' => {
+                if *i + 1 < chars.len() && chars[*i + 1] == '^' {
+                    let (class, end_idx) = parse_char_class(chars, *i + 2)?;
+                    result.push(RE::NegCharClass(class));
+                    *i = end_idx;
+                } else {
+                    let (class, end_idx) = parse_char_class(chars, *i + 1)?;
+                    result.push(RE::CharClass(class));
+                    *i = end_idx;
+                }
+            }
+            '(' if chars[*i..].starts_with(&['(', '?', '#']) => skip_comment(chars, i)?,
+            '(' => {
+                *i += 1; // Move past '('
+                let (group, end_idx) = parse_alternation(chars, *i, flags, group_count)?;
+                result.push(group);
+                *i = end_idx;
+            }
+            quantifier @ ('?' | '+' | '*') => {
+                let inner = Box::new(result.pop().ok_or(Error::EmptyQuantifier(quantifier))?);
+                let (min, max) = match quantifier {
+                    '?' => (0, Some(1)),
+                    '+' => (1, None),
+                    _ => (0, None),
+                };
+                let greediness = parse_quantifier_suffix(chars, i);
+                result.push(RE::Repeat { inner, min, max, greediness });
+            }
+            '{' => match parse_repeat(chars, *i + 1) {
+                Some((min, Some(max), _)) if min > max => return Err(Error::InvalidRepeatCount(min, max)),
+                Some((min, max, end_idx)) => {
+                    *i = end_idx;
+                    let inner = Box::new(result.pop().ok_or(Error::EmptyQuantifier('{'))?);
+                    let greediness = parse_quantifier_suffix(chars, i);
+                    result.push(RE::Repeat { inner, min, max, greediness });
+                }
+                // Not a valid counted quantifier, so treat the brace literally
+                None => result.push(RE::Char('{')),
+            },
+            ch => result.push(RE::Char(ch)),
+        }
+        *i += 1;
+    }
+
+    Ok(result)
+}
+
+// Parses the condition of a conditional group, the `?(N)` in `(?(N)yes|no)`, starting at
+// `chars[*i]`. Leaves `*i` after it and returns the group number, or returns None and leaves
+// `*i` alone if there is no condition.
+fn parse_condition(chars: &[char], i: &mut usize) -> Option<usize> {
+    if !chars[*i..].starts_with(&['?', '(']) {
+        return None;
+    }
+    let digits = chars[*i + 2..].iter().take_while(|c| c.is_ascii_digit()).count();
+    if digits == 0 || chars.get(*i + 2 + digits) != Some(&')') {
+        return None;
+    }
+    let group = chars[*i + 2..*i + 2 + digits].iter().collect::<String>().parse().ok()?;
+    *i += digits + 3;
+    Some(group)
+}
+
+// Skips a (?#..) comment starting at `chars[*i]`, leaving `*i` on its ')'. The comment ends at
+// the first ')', so it cannot contain one.
+fn skip_comment(chars: &[char], i: &mut usize) -> Result<(), Error> {
+    let len = chars[*i..].iter().position(|&c| c == ')').ok_or(Error::UnmatchedParen)?;
+    *i += len;
+    Ok(())
+}
+
+// Pushes the characters of a \Q..\E quote starting at `chars[*i]` as literals, leaving `*i` on
+// the 'E'. Without a closing \E the quote runs to the end of the pattern.
+fn parse_quoted(chars: &[char], i: &mut usize, result: &mut Vec<RE>) {
+    *i += 2;
+    while *i < chars.len() {
+        if chars[*i..].starts_with(&['\\', 'E']) {
+            *i += 1;
+            return;
+        }
+        result.push(RE::Char(chars[*i]));
+        *i += 1;
+    }
+}
+
+// Skips whitespace or a '#' comment (up to the end of the line) at `chars[*i]` in free-spacing mode.
+// Returns false if there was nothing to skip.
+fn skip_free_spacing(chars: &[char], i: &mut usize) -> bool {
+    if chars[*i].is_whitespace() {
+        *i += 1;
+        true
+    } else if chars[*i] == '#' {
+        while *i < chars.len() && chars[*i] != '\n' {
+            *i += 1;
+        }
+        true
+    } else {
+        false
+    }
+}
+
+// Parses the escape sequence whose backslash is at `chars[*i]`, leaving `*i` on its last character.
+pub(crate) fn parse_escape(chars: &[char], i: &mut usize) -> Result<RE, Error> {
+    if *i + 1 >= chars.len() {
+        return Err(Error::TrailingBackslash);
+    }
+    *i += 1;
+    let re = match chars[*i] {
+        'd' => RE::Digit,
+        'w' => RE::Word,
+        'D' => RE::NonDigit,
+        'W' => RE::NonWord,
+        's' => RE::Space,
+        'S' => RE::NonSpace,
+        #[cfg(feature = "unicode")]
+        'p' | 'P' => parse_property(chars, i)?,
+        'b' => RE::WordBoundary,
+        'B' => RE::NonWordBoundary,
+        'A' => RE::AbsoluteStart,
+        'z' => RE::AbsoluteEnd,
+        'Z' => RE::AbsoluteEndOrNewline,
+        'K' => RE::KeepStart,
+        'G' => RE::ContinuationAnchor,
+        '1'..='9' => {
+            // All the digits that follow belong to the group number, so \10 is group 10
+            let digits = chars[*i..].iter().take_while(|c| c.is_ascii_digit()).count();
+            let number: String = chars[*i..*i + digits].iter().collect();
+            *i += digits - 1;
+            RE::Backreference(number.parse().map_err(|_| Error::InvalidBackreference(usize::MAX))?)
+        }
+        // Metacharacters stand for themselves once escaped
+        c @ ('\\' | '.' | '*' | '+' | '?' | '^' | '$' | '|' | '(' | ')' | '{' | '}' | '[' | ']' | '-') => RE::Char(c),
+        ' ' => RE::Char(' '),
+        '#' => RE::Char('#'),
+        't' => RE::Char('\t'),
+        'n' => RE::Char('\n'),
+        'r' => RE::Char('\r'),
+        'a' => RE::Char('\x07'),
+        'f' => RE::Char('\x0C'),
+        'e' => RE::Char('\x1B'),
+        'k' => {
+            *i += 1;
+            RE::NamedBackreference(parse_group_name(chars, i)?)
+        }
+        'x' => RE::Char(parse_hex_escape(chars, i, 2)?),
+        'u' => RE::Char(parse_hex_escape(chars, i, 4)?),
+        'U' => RE::Char(parse_hex_escape(chars, i, 8)?),
+        other => return Err(Error::UnsupportedEscape(other)),
+    };
+    Ok(re)
+}
+
+// Parses the property after \p or \P, either a single letter as in \pL or a name in braces as in
+// \p{Letter}, leaving `*i` on its last character.
+#[cfg(feature = "unicode")]
+fn parse_property(chars: &[char], i: &mut usize) -> Result<RE, Error> {
+    let negated = chars[*i] == 'P';
+    let name: String = match chars.get(*i + 1) {
+        Some('{') => {
+            let len = chars[*i + 2..].iter().position(|&c| c == '}');
+            let name_end = *i + 2 + len.unwrap_or(chars.len() - *i - 2);
+            let name = chars[*i + 2..name_end].iter().collect();
+            if len.is_none() {
+                return Err(Error::UnknownProperty(name));
+            }
+            *i = name_end;
+            name
+        }
+        Some(&c) => {
+            *i += 1;
+            c.to_string()
+        }
+        None => return Err(Error::UnknownProperty(String::new())),
+    };
+    match UnicodeProperty::from_name(&name) {
+        Some(property) => Ok(RE::UnicodeProperty(property, negated)),
+        None => Err(Error::UnknownProperty(name)),
+    }
+}
+
+// Reads exactly `digits` hex digits after `chars[*i]` as a code point, leaving `*i` on the last digit.
+// \x also takes from one to eight digits in braces, as in \x{263A}, leaving `*i` on the '}'.
+pub(crate) fn parse_hex_escape(chars: &[char], i: &mut usize, digits: usize) -> Result<char, Error> {
+    let escape = chars[*i];
+    let mut code_point = 0;
+    if escape == 'x' && chars.get(*i + 1) == Some(&'{') {
+        let len = chars[*i + 2..].iter().position(|&c| c == '}');
+        let hex = &chars[*i + 2..*i + 2 + len.unwrap_or(0)];
+        if !(1..=8).contains(&hex.len()) || !hex.iter().all(char::is_ascii_hexdigit) {
+            return Err(Error::InvalidHexEscape(escape, digits));
+        }
+        code_point = hex.iter().fold(0, |value, c| value * 16 + c.to_digit(16).unwrap_or(0));
+        *i += hex.len() + 2;
+        return char::from_u32(code_point).ok_or(Error::InvalidCodePoint(escape, code_point));
+    }
+    for _ in 0..digits {
+        match chars.get(*i + 1).and_then(|c| c.to_digit(16)) {
+            Some(digit) => code_point = code_point * 16 + digit,
+            None => return Err(Error::InvalidHexEscape(escape, digits)),
+        }
+        *i += 1;
+    }
+    // Surrogates and values past U+10FFFF are not characters
+    char::from_u32(code_point).ok_or(Error::InvalidCodePoint(escape, code_point))
+}
+
+// Parses a group name written as "<name>" with `chars[*i]` on the '<', leaving `*i` on the '>'.
+pub(crate) fn parse_group_name(chars: &[char], i: &mut usize) -> Result<String, Error> {
+    if chars.get(*i) != Some(&'<') {
+        return Err(Error::InvalidGroupName);
+    }
+    let start = *i + 1;
+    match chars[start..].iter().position(|&c| c == '>') {
+        Some(len) if len > 0 => {
+            *i = start + len;
+            Ok(chars[start..*i].iter().collect())
+        }
+        _ => Err(Error::InvalidGroupName),
+    }
+}
+
+pub(crate) fn parse_alternation(
+    chars: &[char],
+    start: usize,
+    flags: &mut Flags,
+    group_count: &mut usize,
+) -> Result<(RE, usize), Error> {
+    let mut i = start;
+
+    // Flags changed inside the group must not outlive it, so the body ends by restoring them
+    let outer_flags = *flags;
+
+    // Inline flags such as (?i) or (?-i) apply from here to the end of the enclosing group,
+    // while scoped flags such as (?i:...) only apply inside a new non-capturing group
+    let mut scoped_flags = false;
+    if chars.get(i) == Some(&'?') {
+        if let Some(len) = chars[i + 1..].iter().position(|&c| c == ')' || c == ':') {
+            let spec = &chars[i + 1..i + 1 + len];
+            if !spec.is_empty() && spec.iter().all(|c| c.is_ascii_alphabetic() || *c == '-') {
+                if !flags.apply(spec) {
+                    return Err(Error::UnsupportedFlag(spec.iter().collect()));
+                }
+                if chars[i + 1 + len] == ')' {
+                    return Ok((RE::SetFlags(*flags), i + 1 + len));
+                }
+                i += len + 2;
+                scoped_flags = true;
+            }
+        }
+    }
+
+    // The syntax right after '(' decides what kind of group this is
+    let kind = if scoped_flags {
+        GroupKind::NonCapturing
+    } else if chars[i..].starts_with(&['?', ':']) {
+        i += 2;
+        GroupKind::NonCapturing
+    } else if chars[i..].starts_with(&['?', '>']) {
+        i += 2;
+        GroupKind::Atomic
+    } else if chars[i..].starts_with(&['?', '=']) {
+        i += 2;
+        GroupKind::LookaheadPos
+    } else if chars[i..].starts_with(&['?', '!']) {
+        i += 2;
+        GroupKind::LookaheadNeg
+    } else if chars[i..].starts_with(&['?', '<', '=']) {
+        i += 3;
+        GroupKind::LookbehindPos
+    } else if chars[i..].starts_with(&['?', '<', '!']) {
+        i += 3;
+        GroupKind::LookbehindNeg
+    } else if let Some(group) = parse_condition(chars, &mut i) {
+        GroupKind::Conditional(group)
+    } else if chars[i..].starts_with(&['?', 'P', '<']) {
+        i += 2;
+        let name = parse_group_name(chars, &mut i)?;
+        i += 1;
+        *group_count += 1;
+        GroupKind::Named(*group_count, name)
+    } else {
+        // The index is taken before the body is parsed, so outer groups come before inner ones
+        *group_count += 1;
+        GroupKind::Capturing(*group_count)
+    };
+    let condition = match kind {
+        GroupKind::Conditional(group) => Some(group),
+        _ => None,
+    };
+    let wrap = |inner: Vec<RE>| match kind {
+        GroupKind::Capturing(index) => Ok(RE::Group(index, inner)),
+        GroupKind::NonCapturing => Ok(RE::NonCapturingGroup(inner)),
+        GroupKind::Named(index, name) => Ok(RE::NamedGroup(index, name, inner)),
+        GroupKind::Atomic => Ok(RE::AtomicGroup(inner)),
+        GroupKind::LookaheadPos => Ok(RE::LookaheadPos(inner)),
+        GroupKind::LookaheadNeg => Ok(RE::LookaheadNeg(inner)),
+        GroupKind::LookbehindPos | GroupKind::LookbehindNeg if fixed_length(&inner).is_none() => {
+            Err(Error::VariableLookbehind)
+        }
+        GroupKind::LookbehindPos => Ok(RE::LookbehindPos(inner)),
+        GroupKind::LookbehindNeg => Ok(RE::LookbehindNeg(inner)),
+        GroupKind::Conditional(_) => unreachable!("conditionals are built from their branches"),
+    };
+
+    let restore = |body: &mut Vec<RE>, flags: &mut Flags| {
+        if *flags != outer_flags {
+            body.push(RE::SetFlags(outer_flags));
+            *flags = outer_flags;
+        }
+    };
+
+    // Each alternative starts with whatever flags were in effect at the end of the one before it
+    let mut alternatives = Vec::new();
+    loop {
+        let alternative_flags = *flags;
+        let mut alternative = parse_sequence(chars, &mut i, flags, group_count)?;
+        if alternative_flags != outer_flags {
+            alternative.insert(0, RE::SetFlags(alternative_flags));
+        }
+        alternatives.push(alternative);
+        if i < chars.len() && chars[i] == '|' {
+            i += 1; // Move past '|'
+        } else {
+            break;
+        }
+    }
+
+    if i < chars.len() && chars[i] == ')' {
+        // The first alternative is the yes branch and the optional second one the no branch
+        if let Some(group) = condition {
+            if alternatives.len() > 2 {
+                return Err(Error::TooManyConditionalBranches);
+            }
+            let mut branches = alternatives.into_iter();
+            let mut yes = branches.next().unwrap();
+            let mut no = branches.next().unwrap_or_default();
+            if *flags != outer_flags {
+                yes.push(RE::SetFlags(outer_flags));
+                no.push(RE::SetFlags(outer_flags));
+                *flags = outer_flags;
+            }
+            return Ok((RE::Conditional { group, yes, no }, i));
+        }
+        let mut body = if alternatives.len() == 1 {
+            alternatives.pop().unwrap()
+        } else {
+            // Wrap the alternation in a group directly
+            vec![RE::Alternation(alternatives)]
+        };
+        restore(&mut body, flags);
+        Ok((wrap(body)?, i))
+    } else {
+        Err(Error::UnmatchedParen)
+    }
+}
+
+// The number of characters `pattern` always consumes, or None if that varies between matches
+fn fixed_length(pattern: &[RE]) -> Option<usize> {
+    pattern.iter().try_fold(0, |total, re| {
+        let length = match re {
+            RE::Char(_)
+            | RE::Dot
+            | RE::CharClass(_)
+            | RE::NegCharClass(_)
+            | RE::Digit
+            | RE::NonDigit
+            | RE::Word
+            | RE::NonWord
+            | RE::Space
+            | RE::NonSpace
+            | RE::UnicodeProperty(..) => 1,
+            RE::Start
+            | RE::End
+            | RE::SetFlags(_)
+            | RE::WordBoundary
+            | RE::NonWordBoundary
+            | RE::AbsoluteStart
+            | RE::AbsoluteEnd
+            | RE::AbsoluteEndOrNewline
+            | RE::KeepStart
+            | RE::ContinuationAnchor
+            | RE::LookaheadPos(_)
+            | RE::LookaheadNeg(_)
+            | RE::LookbehindPos(_)
+            | RE::LookbehindNeg(_) => 0,
+            RE::Group(_, inner) | RE::NonCapturingGroup(inner) | RE::NamedGroup(_, _, inner) | RE::AtomicGroup(inner) => {
+                fixed_length(inner)?
+            }
+            RE::Alternation(alternatives) => {
+                let length = fixed_length(&alternatives[0])?;
+                for alternative in &alternatives[1..] {
+                    if fixed_length(alternative)? != length {
+                        return None;
+                    }
+                }
+                length
+            }
+            RE::Conditional { yes, no, .. } if fixed_length(yes)? == fixed_length(no)? => fixed_length(yes)?,
+            RE::Repeat { inner, min, max: Some(max), .. } if min == max => {
+                fixed_length(core::slice::from_ref(inner.as_ref()))? * min
+            }
+            _ => return None,
+        };
+        Some(total + length)
+    })
+}
+
+// Simplifies a parsed pattern without changing what it matches. For now that means replacing a
+// non-capturing group around a single node with the node itself, so that (?:a)+ becomes a+ and
+// takes the fast path for single-character repetitions.
+fn optimize(pattern: Vec<RE>) -> Vec<RE> {
+    pattern.into_iter().map(optimize_node).collect()
+}
+
+fn optimize_node(re: RE) -> RE {
+    match re {
+        RE::NonCapturingGroup(inner) => {
+            let mut inner = optimize(inner);
+            // An alternation needs the group to keep its alternatives apart from what surrounds
+            // it, and flags set inside the group must not leak out of it
+            if inner.len() == 1 && !matches!(inner[0], RE::Alternation(_) | RE::SetFlags(_)) {
+                inner.pop().unwrap()
+            } else {
+                RE::NonCapturingGroup(inner)
+            }
+        }
+        RE::Repeat { inner, min, max, greediness } => RE::Repeat {
+            inner: Box::new(optimize_node(*inner)),
+            min,
+            max,
+            greediness,
+        },
+        RE::Group(index, inner) => RE::Group(index, optimize(inner)),
+        RE::NamedGroup(index, name, inner) => RE::NamedGroup(index, name, optimize(inner)),
+        RE::AtomicGroup(inner) => RE::AtomicGroup(optimize(inner)),
+        RE::LookaheadPos(inner) => RE::LookaheadPos(optimize(inner)),
+        RE::LookaheadNeg(inner) => RE::LookaheadNeg(optimize(inner)),
+        RE::LookbehindPos(inner) => RE::LookbehindPos(optimize(inner)),
+        RE::LookbehindNeg(inner) => RE::LookbehindNeg(optimize(inner)),
+        RE::Conditional { group, yes, no } => RE::Conditional { group, yes: optimize(yes), no: optimize(no) },
+        RE::Alternation(alternatives) => RE::Alternation(alternatives.into_iter().map(optimize).collect()),
+        re => re,
+    }
+}
+
+// How deeply the groups in `pattern` nest. Escaped parentheses and those inside character
+// classes do not count.
+fn group_nesting(pattern: &str) -> usize {
+    let (mut depth, mut deepest) = (0usize, 0);
+    let mut in_class = false;
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '[' => in_class = true,
+            ']' => in_class = false,
+            '(' if !in_class => {
+                depth += 1;
+                deepest = deepest.max(depth);
+            }
+            ')' if !in_class => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    deepest
+}
+
+// Whether `pattern` looks at what was captured anywhere, through a backreference or a
+// conditional, including inside groups and quantifiers
+fn depends_on_captures(pattern: &[RE]) -> bool {
+    find_node(pattern, &|re| matches!(re, RE::Backreference(_) | RE::NamedBackreference(_) | RE::Conditional { .. }))
+        .is_some()
+}
+
+// The first node in `pattern` for which `predicate` holds, looking inside groups and quantifiers
+fn find_node<'p>(pattern: &'p [RE], predicate: &impl Fn(&RE) -> bool) -> Option<&'p RE> {
+    pattern.iter().find_map(|re| {
+        if predicate(re) {
+            return Some(re);
+        }
+        match re {
+            RE::Repeat { inner, .. } => find_node(core::slice::from_ref(inner.as_ref()), predicate),
+            RE::Group(_, inner)
+            | RE::NonCapturingGroup(inner)
+            | RE::NamedGroup(_, _, inner)
+            | RE::AtomicGroup(inner)
+            | RE::LookaheadPos(inner)
+            | RE::LookaheadNeg(inner)
+            | RE::LookbehindPos(inner)
+            | RE::LookbehindNeg(inner) => find_node(inner, predicate),
+            RE::Conditional { yes, no, .. } => find_node(yes, predicate).or_else(|| find_node(no, predicate)),
+            RE::Alternation(alternatives) => alternatives.iter().find_map(|alternative| find_node(alternative, predicate)),
+            _ => None,
+        }
+    })
+}
+
+// Consumes a '?' or '+' directly after a quantifier, which makes that quantifier lazy or possessive.
+pub(crate) fn parse_quantifier_suffix(chars: &[char], i: &mut usize) -> Greediness {
+    match chars.get(*i + 1) {
+        Some('?') => {
+            *i += 1;
+            Greediness::Lazy
+        }
+        Some('+') => {
+            *i += 1;
+            Greediness::Possessive
+        }
+        _ => Greediness::Greedy,
+    }
+}
+
+// Parses the body of a counted quantifier starting just after '{': "n}", "n,}" or "n,m}".
+// Returns the bounds and the index of the closing '}', or None if the syntax is not a quantifier.
+pub(crate) fn parse_repeat(chars: &[char], start: usize) -> Option<(usize, Option<usize>, usize)> {
+    let mut i = start;
+    let read_number = |i: &mut usize| -> Option<usize> {
+        let digits_start = *i;
+        while *i < chars.len() && chars[*i].is_ascii_digit() {
+            *i += 1;
+        }
+        chars[digits_start..*i].iter().collect::<String>().parse().ok()
+    };
+
+    let min = read_number(&mut i)?;
+    let max = if i < chars.len() && chars[i] == ',' {
+        i += 1;
+        if i < chars.len() && chars[i] == '}' {
+            None
+        } else {
+            Some(read_number(&mut i)?)
+        }
+    } else {
+        Some(min)
+    };
+
+    if i < chars.len() && chars[i] == '}' {
+        Some((min, max, i))
+    } else {
+        None
+    }
+}
+
+pub(crate) fn parse_char_class(chars: &[char], start: usize) -> Result<(CharClass, usize), Error> {
+    let mut class = Vec::new();
+    let mut i = start;
+
+    while i < chars.len() {
+        if chars[i] == ']' {
+            return Ok((CharClass::new(class), i));
+        }
+        let item = parse_class_item(chars, &mut i)?;
+        if let ClassItem::Char(start) = item {
+            if i + 2 < chars.len() && chars[i + 1] == '-' && chars[i + 2] != ']' {
+                // Handle range like a-z
+                i += 2;
+                match parse_class_item(chars, &mut i)? {
+                    // A range that ends before it starts, like z-a, is a mistake rather than empty
+                    ClassItem::Char(end) if start <= end => class.push(ClassItem::Range(start, end)),
+                    _ => return Err(Error::InvalidClassRange),
+                }
+            } else {
+                class.push(item);
+            }
+        } else {
+            class.push(item);
+        }
+        i += 1;
+    }
+
+    Err(Error::UnterminatedClass)
+}
+
+// Parses the character or escape at `chars[*i]` inside a class, leaving `*i` on its last character.
+pub(crate) fn parse_class_item(chars: &[char], i: &mut usize) -> Result<ClassItem, Error> {
+    if chars[*i..].starts_with(&['[', ':']) {
+        let name_start = *i + 2;
+        let name_len = chars[name_start..]
+            .windows(2)
+            .position(|w| w == [':', ']'])
+            .ok_or(Error::UnterminatedClass)?;
+        let name: String = chars[name_start..name_start + name_len].iter().collect();
+        *i = name_start + name_len + 1;
+        return PosixClass::from_name(&name).map(ClassItem::Posix).ok_or(Error::UnknownPosixClass(name));
+    }
+    if chars[*i] != '\\' {
+        return Ok(ClassItem::Char(chars[*i]));
+    }
+    match parse_escape(chars, i)? {
+        RE::Char(c) => Ok(ClassItem::Char(c)),
+        re @ (RE::Digit | RE::NonDigit | RE::Word | RE::NonWord | RE::Space | RE::NonSpace | RE::UnicodeProperty(..)) => {
+            Ok(ClassItem::Shorthand(re))
+        }
+        _ => Err(Error::UnsupportedEscape(chars[*i])),
+    }
+}
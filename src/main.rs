@@ -1,468 +1,868 @@
-use std::collections::HashMap;
+use std::collections::VecDeque;
 use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, IsTerminal, Write};
+use std::path::Path;
 use std::process;
 
-#[derive(Debug, Clone, PartialEq)]
-enum RE {
-    Char(char),                 // A literal character
-    Question(Box<RE>),          // A character or regex type followed by '?'
-    Plus(Box<RE>),              // A character or regex type followed by '+'
-    Dot,                        // The '.' metacharacter
-    Start,                      // The '^' metacharacter
-    End,                        // The '$' metacharacter
-    CharClass(Vec<char>),       // A character class, e.g., [a-z]
-    NegCharClass(Vec<char>),    // A negated character class, e.g., [^a-z]
-    Digit,                      // Shorthand for \d (any digit)
-    Word,                       // Shorthand for \w (alphanumeric character)
-    Alternation(Vec<RE>, Vec<RE>), // Alternation between two patterns, e.g., (cat|dog)
-    Group(Vec<RE>),             // A grouped sub-pattern, e.g., (cat)
-    Backreference(usize),       // A backreference to a previously captured group, e.g., \1
-}
+use mygrep::{read_line, Captures, Match, RegexBuilder, RegexEngine, RegexSet};
 
-#[derive(Clone)]
-struct MatchContext<'a> {
-    text: &'a str,
-    captures: HashMap<usize, String>,
-    group_index: usize,
+// What to do with the lines each input yields
+struct Options {
+    invert: bool,              // -v: select the lines that do not match
+    count: bool,               // -c: print how many lines were selected instead of the lines
+    line_number: bool,         // -n: prefix each line with its line number
+    only_matching: bool,       // -o: print each match instead of the whole line
+    files_with_matches: bool,  // -l: print the names of inputs with a selected line
+    null: bool,                // -Z: end file names with a NUL byte instead of ':' or a newline
+    files_without_match: bool, // -L: print the names of inputs without a selected line
+    with_filename: bool,       // -H/-h: prefix output with the input's name
+    color: Option<String>,     // --color: the SGR parameters to highlight matches with, if any
+    before_context: usize,     // -B: how many lines to print before each selected line
+    after_context: usize,      // -A: how many lines to print after each selected line
+    max_count: Option<usize>,  // -m: stop reading an input after this many selected lines
+    json: bool,                // --json: print each match as a line of JSON
 }
 
-impl<'a> MatchContext<'a> {
-    fn new(text: &'a str) -> Self {
-        Self {
-            text,
-            captures: HashMap::new(),
-            group_index: 0,
-        }
+// The text printed before a line: its input's name and line number as asked for, each followed
+// by `separator`, which is ':' for selected lines, '-' for context lines before them and '+' for
+// context lines after them
+fn line_prefix(options: &Options, name: &str, line_number: usize, separator: char) -> String {
+    let mut prefix = String::new();
+    if options.with_filename {
+        prefix += &format!("{}{}", name, if options.null { '\0' } else { separator });
+    }
+    if options.line_number {
+        prefix += &format!("{}{}", line_number, separator);
     }
+    prefix
+}
 
-    fn match_pattern(&mut self, pattern: &[RE]) -> bool {
-        if let Some(RE::Start) = pattern.get(0) {
-            self.match_here(&pattern[1..])
-        } else {
-            let mut text_slice = self.text;
-            loop {
-                let mut local_context = self.clone();
-                local_context.text = text_slice;
-
-                if local_context.match_here(pattern) {
-                    *self = local_context; // Update self with successful match state
-                    return true;
-                }
+const USAGE: &str = "Usage: your_program [OPTION]... (-E PATTERN | -e PATTERN | -f FILE)... [FILE]...";
 
-                if text_slice.is_empty() {
-                    break;
+const HELP: &str = "\
+Search each FILE, or standard input, for lines matching any of the PATTERNs.
+
+Patterns:
+  -E, --extended-regexp=PATTERN  use PATTERN for matching
+  -e, --regexp=PATTERN           same as -E
+  -f, --file=FILE                take patterns from FILE, one per line
+  -F, --fixed-strings            match the PATTERNs as literal strings
+  -i, --ignore-case              ignore case distinctions
+  -w, --word-regexp              only match whole words
+  -M, --multiline                '^' and '$' match at line breaks
+      --dotall                   '.' also matches a line break
+
+Output:
+  -v, --invert-match             select non-matching lines
+  -c, --count                    print only a count of selected lines
+  -m, --max-count=NUM            stop reading an input after NUM selected lines
+  -n, --line-number              print line numbers
+  -o, --only-matching            print only the matched parts of lines
+  -l, --files-with-matches       print only names of files with selected lines
+  -L, --files-without-match      print only names of files without selected lines
+  -H, --with-filename            print file names
+  -h, --no-filename              do not print file names
+  -Z, --null                     print a NUL byte after file names
+      --json                     print each match as a JSON object on its own line
+  -A, --after-context=NUM        print NUM lines after each selected line, marked with '+'
+  -B, --before-context=NUM       print NUM lines before each selected line, marked with '-'
+  -C, --context=NUM              print NUM lines before and after
+      --color[=WHEN]             highlight matches; WHEN is 'always', 'never' or 'auto'
+      --line-buffered            flush output after every line
+
+Files:
+  -r, --recursive                search directories recursively
+  -R, --dereference-recursive    same, following symbolic links
+      --include=GLOB             only search files whose names match GLOB
+      --exclude=GLOB             skip files whose names match GLOB
+  -j, --jobs=NUM                 search NUM files at once, or one per CPU if NUM is 0; only
+                                 when built with the parallel feature
+      --binary-files=TYPE        how to treat files with a NUL byte: 'binary' (the default)
+                                 says whether they match, 'text' searches them like any
+                                 other file and 'without-match' skips them
+
+Other:
+      --debug                    print the parsed patterns to standard error
+      --dump-pattern             print the parsed patterns as Graphviz graphs and exit
+      --help                     print this help and exit
+      --version                  print the version and exit
+
+Exit status is 0 if a line was selected, 1 if none was, and 2 if an error occurred.";
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    eprintln!("Try '--help' for more information.");
+    process::exit(2);
+}
+
+// Long options that take a value, which may come as the next argument
+const LONG_WITH_VALUE: [&str; 11] = [
+    "--extended-regexp",
+    "--regexp",
+    "--file",
+    "--after-context",
+    "--before-context",
+    "--context",
+    "--max-count",
+    "--jobs",
+    "--include",
+    "--exclude",
+    "--binary-files",
+];
+
+// Whether `arg` is an option that gives patterns, other than -E
+fn gives_patterns(arg: &str) -> bool {
+    ["-e", "-f", "--regexp", "--file"].contains(&arg) || arg.starts_with("--regexp=") || arg.starts_with("--file=")
+}
+
+// Splits bundled short options such as "-in" into "-i" "-n". An option that takes a value ends
+// the bundle, with the rest of the argument as its value, as in "-A2" or "-ecat". If nothing is
+// left for the value, the next argument is the value and is passed on as it is, even if it
+// starts with '-', as in "-e -foo". Everything after "--" is left alone.
+fn expand_short_options(args: impl Iterator<Item = String>) -> Vec<String> {
+    let mut expanded = Vec::new();
+    let mut args = args.peekable();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            expanded.push(arg);
+            expanded.extend(args.by_ref());
+            break;
+        }
+        // As in GNU grep, -E right before another option that gives patterns only asks for
+        // extended syntax, which is the only syntax there is here, so it is dropped
+        if (arg == "-E" || arg == "--extended-regexp") && args.peek().is_some_and(|next| gives_patterns(next)) {
+            continue;
+        }
+        if arg.starts_with("--") || !arg.starts_with('-') || arg.len() < 2 {
+            let takes_value = LONG_WITH_VALUE.contains(&arg.as_str());
+            expanded.push(arg);
+            if takes_value {
+                expanded.extend(args.next());
+            }
+            continue;
+        }
+        for (i, c) in arg.char_indices().skip(1) {
+            expanded.push(format!("-{}", c));
+            if "EefABCmj".contains(c) {
+                let rest = &arg[i + c.len_utf8()..];
+                if rest.is_empty() {
+                    expanded.extend(args.next());
+                } else {
+                    expanded.push(rest.to_string());
                 }
-                text_slice = &text_slice[1..];
+                break;
             }
-            false
         }
     }
+    expanded
+}
 
-    fn match_here(&mut self, pattern: &[RE]) -> bool {
-        if pattern.is_empty() {
-            return true;
+// Whether any of the patterns matches `line`. A search that gives up, having run past the
+// backtracking or depth limit, is an error rather than a line that does not match.
+fn is_match(patterns: &RegexSet, line: &str) -> io::Result<bool> {
+    for engine in patterns.engines() {
+        if engine.try_find(line).map_err(io::Error::other)?.is_some() {
+            return Ok(true);
         }
+    }
+    Ok(false)
+}
 
-        match &pattern[0] {
-            RE::End => self.text.is_empty(),
-            RE::Char(c) => {
-                if !self.text.is_empty() && self.text.chars().next() == Some(*c) {
-                    let mut local_context = self.clone();
-                    local_context.text = &self.text[1..];
-                    if local_context.match_here(&pattern[1..]) {
-                        *self = local_context; // Update self with successful match state
-                        return true;
-                    }
-                }
-                false
-            }
-            RE::Dot => {
-                if !self.text.is_empty() {
-                    let mut local_context = self.clone();
-                    local_context.text = &self.text[1..];
-                    if local_context.match_here(&pattern[1..]) {
-                        *self = local_context;
-                        return true;
-                    }
-                }
-                false
-            }
-            RE::Question(boxed_re) => {
-                if self.match_here(&pattern[1..]) {
-                    return true;
-                }
-                let mut local_context = self.clone();
-                if !self.text.is_empty() && local_context.matches_char(boxed_re, self.text.chars().next().unwrap()) {
-                    local_context.text = &self.text[1..];
-                    if local_context.match_here(&pattern[1..]) {
-                        *self = local_context;
-                        return true;
-                    }
-                }
-                false
-            }
-            RE::Plus(boxed_re) => {
-                let mut local_context = self.clone();
-                if !self.text.is_empty() && local_context.matches_char(boxed_re, self.text.chars().next().unwrap()) {
-                    local_context.text = &self.text[1..];
-                    loop {
-                        if local_context.match_here(&pattern[1..]) {
-                            *self = local_context;
-                            return true;
-                        }
-                        if local_context.text.is_empty() || !local_context.matches_char(boxed_re, local_context.text.chars().next().unwrap()) {
-                            break;
-                        }
-                        local_context.text = &local_context.text[1..];
-                    }
-                }
-                false
-            }
-            RE::CharClass(class) => {
-                if !self.text.is_empty() && class.contains(&self.text.chars().next().unwrap()) {
-                    let mut local_context = self.clone();
-                    local_context.text = &self.text[1..];
-                    if local_context.match_here(&pattern[1..]) {
-                        *self = local_context;
-                        return true;
-                    }
+// The matches of all the patterns in `line`, from left to right. Where matches overlap, the one
+// that starts first wins, or the longer one if they start at the same place.
+fn find_all<'t>(patterns: &RegexSet, line: &'t str) -> Vec<Match<'t>> {
+    let mut matches: Vec<_> = patterns.engines().iter().flat_map(|engine| engine.find_iter(line)).collect();
+    matches.sort_by_key(|m| (m.start(), std::cmp::Reverse(m.end())));
+    let mut last_end = 0;
+    matches.retain(|m| {
+        let keep = m.start() >= last_end;
+        if keep {
+            last_end = m.end();
+        }
+        keep
+    });
+    matches
+}
+
+// Like find_all, but with what each match's groups captured
+fn find_all_captures<'t>(patterns: &RegexSet, line: &'t str) -> Vec<(Captures<'t>, usize)> {
+    let mut matches: Vec<_> = patterns
+        .engines()
+        .iter()
+        .flat_map(|engine| engine.captures_iter(line).map(|caps| (caps, engine.captures_len())))
+        .collect();
+    matches.sort_by_key(|(caps, _)| (caps.whole().start(), std::cmp::Reverse(caps.whole().end())));
+    let mut last_end = 0;
+    matches.retain(|(caps, _)| {
+        let keep = caps.whole().start() >= last_end;
+        if keep {
+            last_end = caps.whole().end();
+        }
+        keep
+    });
+    matches
+}
+
+// One match as a JSON object: where it is, what it matched and what each of the pattern's
+// `groups` groups captured, with null for groups that did not take part
+fn json_match(name: &str, line_number: usize, (caps, groups): &(Captures, usize)) -> String {
+    let captures: Vec<_> = (1..=*groups).map(|index| caps.get(index).map_or("null".to_string(), json_string)).collect();
+    format!(
+        "{{\"file\":{},\"line\":{},\"match\":{},\"start\":{},\"end\":{},\"captures\":[{}]}}",
+        json_string(name),
+        line_number,
+        json_string(caps.whole().as_str()),
+        caps.whole().start(),
+        caps.whole().end(),
+        captures.join(",")
+    )
+}
+
+// A JSON string literal holding `text`
+fn json_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// Wraps each non-empty match in `text` in the ANSI escape sequence for `color`
+fn highlight(matches: &[Match], text: &str, color: &str) -> String {
+    let mut result = String::new();
+    let mut last_end = 0;
+    for m in matches.iter().filter(|m| m.start() < m.end()) {
+        result += &text[last_end..m.start()];
+        result += &format!("\x1b[{}m{}\x1b[0m", color, m.as_str());
+        last_end = m.end();
+    }
+    result += &text[last_end..];
+    result
+}
+
+// The match color from GREP_COLORS, a ':'-separated list such as "ms=01;31:ln=32" where "ms"
+// (or "mt") sets the color of matches. Other entries are accepted but ignored.
+fn match_color() -> String {
+    let grep_colors = env::var("GREP_COLORS").unwrap_or_default();
+    grep_colors
+        .split(':')
+        .filter_map(|entry| entry.split_once('='))
+        .filter(|(key, _)| *key == "ms" || *key == "mt")
+        .map(|(_, value)| value.to_string())
+        .next_back()
+        .unwrap_or_else(|| "1;31".to_string())
+}
+
+// Searches one input line by line, writing what `options` asks for to `out`, and returns how many
+// lines were selected. A line matches if any of the patterns does.
+fn search(patterns: &RegexSet, name: &str, mut reader: impl BufRead, options: &Options, out: &mut impl Write) -> io::Result<usize> {
+    // Listing file names only needs to know whether any line is selected
+    let first_only = options.files_with_matches || options.files_without_match;
+    // Context lines go around whole selected lines only
+    let context = (options.before_context > 0 || options.after_context > 0)
+        && !options.count
+        && !options.only_matching
+        && !options.json;
+    let mut before = VecDeque::with_capacity(options.before_context);
+    let mut after_remaining = 0;
+    let mut last_printed = None; // Line number of the last line printed, to spot gaps
+    // Context lines are marked with their separator even when there is no name or number to
+    // separate, so that they can be told apart from selected lines
+    let print_context = |out: &mut dyn Write, line_number: usize, line: &[u8], separator: char, last_printed: &mut Option<usize>| {
+        if last_printed.is_some_and(|last| line_number > last + 1) {
+            writeln!(out, "--")?;
+        }
+        let prefix = line_prefix(options, name, line_number, separator);
+        if prefix.is_empty() {
+            write!(out, "{}", separator)?;
+        } else {
+            write!(out, "{}", prefix)?;
+        }
+        out.write_all(line)?;
+        writeln!(out)?;
+        *last_printed = Some(line_number);
+        io::Result::Ok(())
+    };
+
+    let mut selected = 0;
+    let mut raw = Vec::new();
+    let mut line_number = 0;
+    loop {
+        // Past the -m limit only the trailing context of the last selected line is left to print
+        let limit_reached = options.max_count.is_some_and(|max| selected >= max);
+        if (limit_reached && after_remaining == 0) || !read_line(&mut reader, &mut raw)? {
+            break;
+        }
+        line_number += 1;
+        // A line that is not valid UTF-8 is matched with the bad bytes replaced, but lines printed
+        // whole are printed as they were read
+        let line = String::from_utf8_lossy(&raw);
+        if limit_reached || is_match(patterns, &line)? == options.invert {
+            if context && after_remaining > 0 {
+                after_remaining -= 1;
+                print_context(out, line_number, &raw, '+', &mut last_printed)?;
+            } else if context && options.before_context > 0 {
+                // Remember the line in case one of the next few is selected
+                if before.len() == options.before_context {
+                    before.pop_front();
                 }
-                false
+                before.push_back((line_number, raw.clone()));
             }
-            RE::NegCharClass(class) => {
-                if !self.text.is_empty() && !class.contains(&self.text.chars().next().unwrap()) {
-                    let mut local_context = self.clone();
-                    local_context.text = &self.text[1..];
-                    if local_context.match_here(&pattern[1..]) {
-                        *self = local_context;
-                        return true;
-                    }
-                }
-                false
+            continue;
+        }
+        selected += 1;
+        if first_only {
+            break;
+        }
+        if options.count {
+            continue;
+        }
+        if options.json {
+            for caps in find_all_captures(patterns, &line) {
+                writeln!(out, "{}", json_match(name, line_number, &caps))?;
             }
-            RE::Digit => {
-                if !self.text.is_empty() && self.text.chars().next().unwrap().is_ascii_digit() {
-                    let mut local_context = self.clone();
-                    local_context.text = &self.text[1..];
-                    if local_context.match_here(&pattern[1..]) {
-                        *self = local_context;
-                        return true;
-                    }
-                }
-                false
+            continue;
+        }
+        if context {
+            for (number, before_line) in before.drain(..) {
+                print_context(out, number, &before_line, '-', &mut last_printed)?;
             }
-            RE::Word => {
-                if !self.text.is_empty() && self.text.chars().next().unwrap().is_alphanumeric() {
-                    let mut local_context = self.clone();
-                    local_context.text = &self.text[1..];
-                    if local_context.match_here(&pattern[1..]) {
-                        *self = local_context;
-                        return true;
-                    }
-                }
-                false
+            if last_printed.is_some_and(|last| line_number > last + 1) {
+                writeln!(out, "--")?;
             }
-            RE::Backreference(group_index) => {
-                //println!("trying group index {} with available captures {:#?}", group_index, self.captures);
-                if let Some(captured) = self.captures.get(group_index) {
-                    if self.text.starts_with(captured) {
-                        let mut local_context = self.clone();
-                        local_context.text = &self.text[captured.len()..];
-                        if local_context.match_here(&pattern[1..]) {
-                            *self = local_context;
-                            return true;
-                        }
-                    }
+            last_printed = Some(line_number);
+            after_remaining = options.after_context;
+        }
+        let prefix = line_prefix(options, name, line_number, ':');
+        if options.only_matching {
+            // Each non-empty match goes on its own line
+            for m in find_all(patterns, &line).iter().filter(|m| m.start() < m.end()) {
+                match &options.color {
+                    Some(color) => writeln!(out, "{}\x1b[{}m{}\x1b[0m", prefix, color, m.as_str())?,
+                    None => writeln!(out, "{}{}", prefix, m.as_str())?,
                 }
-                false
             }
-            RE::Group(group_pattern) => {
-                let original_captures = self.captures.clone();
-                let original_group_index = self.group_index;
-    
-                // Increment group index
-                self.group_index += 1;
-                let group_index = self.group_index;
-    
-                // We reverse the loop to ensure greedy matching: longest match first
-                for len in (0..=self.text.len()).rev() {  // <-- Change here to iterate from longest to shortest
-                    let slice = &self.text[..len];
-                    let mut local_context = self.clone();
-                    local_context.text = slice;
-    
-                    // Attempt to match the group
-                    if local_context.match_pattern(group_pattern) {
-                        // If the group matched, capture its content
-                        local_context.captures.insert(group_index, slice.to_string());
-    
-                        // Continue matching the remaining pattern with the rest of the text
-                        local_context.text = &self.text[len..];
-                        if local_context.match_here(&pattern[1..]) {
-                            *self = local_context; // Update self with successful match state
-                            return true;
-                        }
-                    }
-                }
-    
-                // Restore captures and group index if no match is found
-                self.captures = original_captures;
-                self.group_index = original_group_index;
-                false
-            },
-            RE::Alternation(left, right) => {
-                let mut local_context = self.clone();
-                if local_context.match_pattern(left) {
-                    *self = local_context;
-                    return true;
-                }
-
-                local_context = self.clone();
-                if local_context.match_pattern(right) {
-                    *self = local_context;
-                    return true;
+        } else {
+            match &options.color {
+                Some(color) => writeln!(out, "{}{}", prefix, highlight(&find_all(patterns, &line), &line, color))?,
+                None => {
+                    out.write_all(prefix.as_bytes())?;
+                    out.write_all(&raw)?;
+                    writeln!(out)?;
                 }
-                false
             }
-            _=> false,
         }
     }
+    Ok(selected)
+}
+
+// What to do with an input that has a NUL byte in its first 8 KB
+#[derive(Default, Clone, Copy, PartialEq)]
+enum BinaryFiles {
+    Text, // Search it like any other input
+    #[default]
+    Binary, // Say whether it matches instead of printing the lines
+    WithoutMatch, // Skip it
+}
+
+// What a recursive search looks at
+#[derive(Default)]
+struct SearchOptions {
+    follow_links: bool,        // -R: follow symbolic links met in directories
+    include: Vec<String>,      // --include: if any are given, a file's name must match one of them
+    exclude: Vec<String>,      // --exclude: files whose names match one of these are skipped
+    binary_files: BinaryFiles, // --binary-files: how to treat binary inputs
+}
+
+impl SearchOptions {
+    fn wants(&self, file_name: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|glob| glob_matches(glob, file_name)))
+            && !self.exclude.iter().any(|glob| glob_matches(glob, file_name))
+    }
+}
 
-    fn matches_char(&self, re: &RE, c: char) -> bool {
-        match re {
-            RE::Char(ch) => *ch == c,
-            RE::Dot => true,
-            RE::Digit => c.is_ascii_digit(),
-            RE::Word => c.is_alphanumeric(),
-            RE::CharClass(class) => class.contains(&c),
-            RE::NegCharClass(class) => !class.contains(&c),
-            _ => false,
+// Matches a file name against a glob where '*' stands for any run of characters, and every
+// other character stands for itself.
+fn glob_matches(pattern: &str, filename: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = filename.chars().collect();
+    // Where to resume if the current attempt fails: the last '*' seen and the name position it
+    // has been stretched to so far
+    let mut star = None;
+    let (mut p, mut n) = (0, 0);
+    while n < name.len() {
+        if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == name[n] {
+            p += 1;
+            n += 1;
+        } else if let Some((star_p, star_n)) = star {
+            star = Some((star_p, star_n + 1));
+            p = star_p + 1;
+            n = star_n + 1;
+        } else {
+            return false;
         }
     }
+    pattern[p..].iter().all(|&c| c == '*')
 }
 
-struct RegexEngine {
-    pattern: Vec<RE>,
+// Adds the files under `path` to `files` in a stable order, descending into directories and
+// keeping the files that `search` wants. Problems are reported as they come up, and the return
+// value says whether there were any.
+fn walk(path: &Path, search: &SearchOptions, files: &mut Vec<String>) -> bool {
+    if !path.is_dir() {
+        let file_name = path.file_name().map_or_else(|| path.to_string_lossy(), |name| name.to_string_lossy());
+        if search.wants(&file_name) {
+            files.push(path.display().to_string());
+        }
+        return true;
+    }
+    let entries = match std::fs::read_dir(path) {
+        Ok(entries) => entries.collect::<io::Result<Vec<_>>>(),
+        Err(err) => Err(err),
+    };
+    let mut entries = match entries {
+        Ok(entries) => entries,
+        Err(err) => {
+            eprintln!("{}: {}", path.display(), err);
+            return false;
+        }
+    };
+    entries.sort_by_key(|entry| entry.file_name());
+
+    let mut ok = true;
+    for entry in entries {
+        let is_link = entry.file_type().is_ok_and(|file_type| file_type.is_symlink());
+        if is_link && !search.follow_links {
+            continue;
+        }
+        ok &= walk(&entry.path(), search, files);
+    }
+    ok
 }
 
-impl RegexEngine {
-    fn new(pattern: &str) -> Self {
-        let parsed_pattern = parse_pattern(pattern);
-        Self {
-            pattern: parsed_pattern,
+// Counts the lines of a binary input that would be selected, without printing any
+fn count_selected(patterns: &RegexSet, mut reader: impl BufRead, options: &Options) -> io::Result<usize> {
+    let limit = if options.files_with_matches || options.files_without_match {
+        1
+    } else {
+        options.max_count.unwrap_or(usize::MAX)
+    };
+    let mut selected = 0;
+    let mut line = Vec::new();
+    while selected < limit && read_line(&mut reader, &mut line)? {
+        if is_match(patterns, &String::from_utf8_lossy(&line))? != options.invert {
+            selected += 1;
         }
     }
+    Ok(selected)
+}
 
-    fn match_text(&self, text: &str) -> bool {
-        let mut context = MatchContext::new(text);
-        context.match_pattern(&self.pattern)
+// Searches one input as `binary_files` says to if it is binary, which it is taken to be if there
+// is a NUL byte in its first 8 KB, and like search otherwise. Returns None if it was skipped.
+fn search_input(
+    patterns: &RegexSet,
+    name: &str,
+    mut reader: impl BufRead,
+    options: &Options,
+    binary_files: BinaryFiles,
+    out: &mut impl Write,
+) -> io::Result<Option<usize>> {
+    if binary_files == BinaryFiles::Text || !reader.fill_buf()?.contains(&0) {
+        return search(patterns, name, reader, options, out).map(Some);
+    }
+    if binary_files == BinaryFiles::WithoutMatch {
+        return Ok(None);
+    }
+    let selected = count_selected(patterns, reader, options)?;
+    // Counts and file names are printed as usual, but lines would be binary junk
+    let prints_lines = !options.count && !options.files_with_matches && !options.files_without_match;
+    if prints_lines && selected > 0 {
+        writeln!(out, "Binary file {} matches", name)?;
     }
+    Ok(Some(selected))
 }
 
-fn parse_pattern(pattern: &str) -> Vec<RE> {
-    let mut result = Vec::new();
-    let chars: Vec<char> = pattern.chars().collect();
-    let mut i = 0;
-
-    while i < chars.len() {
-        match chars[i] {
-            '^' => result.push(RE::Start),
-            '$' => result.push(RE::End),
-            '.' => result.push(RE::Dot),
-            '\\' => {
-                if i + 1 < chars.len() {
-                    match chars[i + 1] {
-                        'd' => result.push(RE::Digit),
-                        'w' => result.push(RE::Word),
-                        '1'..='9' => {
-                            let group_index = chars[i + 1].to_digit(10).unwrap() as usize;
-                            result.push(RE::Backreference(group_index));
-                        }
-                        '\\' => result.push(RE::Char('\\')),
-                        _ => panic!("Unsupported escape sequence: \\{}", chars[i + 1]),
-                    }
-                    i += 1;
-                } else {
-                    panic!("Pattern ends with an incomplete escape sequence");
-                }
+// Searches the input named `file`, '-' being standard input, and writes what `options` asks for
+// to `out`: its lines, its count or its name. Returns whether it counts towards success, which with
+// -l or -L means its name was printed and otherwise that some line was selected.
+fn search_file(
+    patterns: &RegexSet,
+    file: &str,
+    options: &Options,
+    binary_files: BinaryFiles,
+    out: &mut impl Write,
+) -> io::Result<bool> {
+    let (name, selected) = if file == "-" {
+        let name = "(standard input)";
+        (name, search_input(patterns, name, io::stdin().lock(), options, binary_files, out)?)
+    } else {
+        let reader = BufReader::with_capacity(8192, File::open(file)?);
+        (file, search_input(patterns, file, reader, options, binary_files, out)?)
+    };
+    let Some(selected) = selected else {
+        return Ok(false);
+    };
+    if options.files_with_matches || options.files_without_match {
+        if (selected > 0) != options.files_with_matches {
+            return Ok(false);
+        }
+        if options.null {
+            write!(out, "{}\0", name)?;
+        } else {
+            writeln!(out, "{}", name)?;
+        }
+        return Ok(true);
+    }
+    if options.count && options.with_filename {
+        writeln!(out, "{}{}{}", name, if options.null { '\0' } else { ':' }, selected)?;
+    } else if options.count {
+        writeln!(out, "{}", selected)?;
+    }
+    Ok(selected > 0)
+}
+
+// Searches `files` one after another. Returns whether any of them counts towards success and
+// whether any could not be read, which has already been reported.
+fn search_all(patterns: &RegexSet, files: &[String], options: &Options, binary_files: BinaryFiles) -> (bool, bool) {
+    let mut out = io::stdout().lock();
+    let (mut success, mut failed) = (false, false);
+    for file in files {
+        match search_file(patterns, file, options, binary_files, &mut out) {
+            Ok(selected) => success |= selected,
+            Err(err) => {
+                eprintln!("{}: {}", file, err);
+                failed = true;
             }
-            '[' => {
-                if i + 1 < chars.len() && chars[i + 1] == '^' {
-                    let (class, end_idx) = parse_char_class(&chars, i + 2);
-                    result.push(RE::NegCharClass(class));
-                    i = end_idx;
-                } else {
-                    let (class, end_idx) = parse_char_class(&chars, i + 1);
-                    result.push(RE::CharClass(class));
-                    i = end_idx;
+        }
+    }
+    (success, failed)
+}
+
+// Like search_all, but searches up to `jobs` files at once. The main thread hands out the files
+// to the workers, which write what they find to a buffer each. A collector prints the buffers in
+// the order of `files`, so the output is the same as without -j.
+#[cfg(feature = "parallel")]
+fn search_parallel(
+    patterns: &RegexSet,
+    files: &[String],
+    options: &Options,
+    binary_files: BinaryFiles,
+    jobs: usize,
+) -> (bool, bool) {
+    use std::collections::BTreeMap;
+    use std::sync::{mpsc, Mutex};
+    use std::thread;
+
+    let (job_sender, job_receiver) = mpsc::channel::<usize>();
+    let job_receiver = Mutex::new(job_receiver);
+    let (result_sender, result_receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            let job_receiver = &job_receiver;
+            let result_sender = result_sender.clone();
+            // Workers search as deeply as the main search thread, so they need as big a stack
+            let worker = thread::Builder::new().stack_size(STACK_SIZE).spawn_scoped(scope, move || loop {
+                // The lock is only held while waiting for the next file
+                let Ok(index) = job_receiver.lock().unwrap().recv() else {
+                    break;
+                };
+                let mut output = Vec::new();
+                let result = search_file(patterns, &files[index], options, binary_files, &mut output);
+                if result_sender.send((index, output, result)).is_err() {
+                    break;
                 }
+            });
+            if let Err(err) = worker {
+                eprintln!("could not start searching: {}", err);
+                process::exit(2);
             }
-            '(' => {
-                // Check if the previous element is RE::Start
-                let prepend_start = result.last() == Some(&RE::Start);
-
-                let (mut group, end_idx) = parse_alternation(&chars, i + 1);
-                // If we should prepend RE::Start, do so
-                if prepend_start{
-                    if let RE::Group(mut group_inner) = group{
-                        group_inner.insert(0, RE::Start);
-                        group = RE::Group(group_inner);
+        }
+        // Only the workers may hold senders, so the collector stops once they are all done
+        drop(result_sender);
+
+        let collector = scope.spawn(move || {
+            let mut out = io::stdout().lock();
+            let mut finished = BTreeMap::new(); // Results that came in before an earlier file's
+            let mut next = 0;
+            let (mut success, mut failed) = (false, false);
+            for (index, output, result) in result_receiver {
+                finished.insert(index, (output, result));
+                while let Some((output, result)) = finished.remove(&next) {
+                    // Whatever was found before a read error is still printed
+                    match out.write_all(&output).and(result) {
+                        Ok(selected) => success |= selected,
+                        Err(err) => {
+                            eprintln!("{}: {}", files[next], err);
+                            failed = true;
+                        }
                     }
-                }
-                
-                result.push(group);
-                i = end_idx;
-            }
-            '?' => {
-                if let Some(last) = result.pop() {
-                    result.push(RE::Question(Box::new(last)));
-                } else {
-                    panic!("Invalid pattern: '?' cannot be the first character");
+                    next += 1;
                 }
             }
-            '+' => {
-                if let Some(last) = result.pop() {
-                    result.push(RE::Plus(Box::new(last)));
-                } else {
-                    panic!("Invalid pattern: '+' cannot be the first character");
-                }
-            }
-            ch => result.push(RE::Char(ch)),
+            (success, failed)
+        });
+
+        for index in 0..files.len() {
+            job_sender.send(index).unwrap();
+        }
+        drop(job_sender);
+        collector.join().unwrap()
+    })
+}
+
+// How deeply a search may recurse, and the stack that leaves room for. A search recurses a few
+// levels for every repetition of a group, so the library's default would give up on lines only
+// a few hundred characters long. The stack is reserved up front but only used as needed.
+const DEPTH_LIMIT: usize = 50_000;
+const STACK_SIZE: usize = 512 << 20;
+
+fn main() {
+    let searcher = std::thread::Builder::new().stack_size(STACK_SIZE).spawn(run);
+    match searcher.map(|searcher| searcher.join()) {
+        Ok(Ok(())) => {}
+        Ok(Err(panic)) => std::panic::resume_unwind(panic),
+        Err(err) => {
+            eprintln!("could not start searching: {}", err);
+            process::exit(2);
         }
-        i += 1;
     }
-    //println!("pattern to match: {:?}", result);
-    result
 }
 
-fn parse_sequence(chars: &[char], i: &mut usize) -> Vec<RE> {
-    let mut result = Vec::new();
-
-    while *i < chars.len() {
-        match chars[*i] {
-            '|' | ')' => break, // Stop when encountering alternation or end of group
-            '^' => result.push(RE::Start),
-            '$' => result.push(RE::End),
-            '.' => result.push(RE::Dot),
-            '\\' => {
-                if *i + 1 < chars.len() {
-                    match chars[*i + 1] {
-                        'd' => result.push(RE::Digit),
-                        'w' => result.push(RE::Word),
-                        '1'..='9' => {
-                            let group_index = chars[*i + 1].to_digit(10).unwrap() as usize;
-                            result.push(RE::Backreference(group_index));
-                        }
-                        '\\' => result.push(RE::Char('\\')),
-                        _ => panic!("Unsupported escape sequence: \\{}", chars[*i + 1]),
+// Usage: echo <input_text> | your_program.sh [options] -E <pattern>
+//        your_program.sh [options] -e <pattern> [-e <pattern>]... <file>...
+fn run() {
+    let mut patterns = Vec::new();
+    let mut pattern_file = false;
+    let mut fixed_strings = false;
+    let mut case_insensitive = false;
+    let mut multiline = false;
+    let mut dotall = false;
+    let mut debug = false;
+    let mut dump_pattern = false;
+    let mut recursive = false;
+    let mut search_options = SearchOptions::default();
+    let mut whole_words = false;
+    #[cfg(feature = "parallel")]
+    let mut jobs = None;
+    let mut files = Vec::new();
+    let mut options = Options {
+        invert: false,
+        count: false,
+        line_number: false,
+        only_matching: false,
+        files_with_matches: false,
+        files_without_match: false,
+        null: false,
+        with_filename: false,
+        color: None,
+        before_context: 0,
+        after_context: 0,
+        max_count: None,
+        json: false,
+    };
+    let mut color = "auto".to_string();
+    let mut with_filename = None; // Decided by the number of inputs unless -H or -h is given
+    let mut args = expand_short_options(env::args().skip(1)).into_iter();
+    while let Some(arg) = args.next() {
+        // Long options can take their value after '=', as in --context=2
+        let (name, inline_value) = match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => (name.to_string(), Some(value.to_string())),
+            _ => (arg.clone(), None),
+        };
+        let mut value = || inline_value.clone().or_else(|| args.next()).unwrap_or_else(|| usage());
+        match name.as_str() {
+            "-E" | "--extended-regexp" | "-e" | "--regexp" => patterns.push(value()),
+            // Patterns from a file, one per line
+            "-f" | "--file" => {
+                let file = value();
+                pattern_file = true;
+                match std::fs::read_to_string(&file) {
+                    Ok(contents) => patterns.extend(contents.lines().map(str::to_string)),
+                    Err(err) => {
+                        eprintln!("{}: {}", file, err);
+                        process::exit(2);
                     }
-                    *i += 1;
-                } else {
-                    panic!("Pattern ends with an incomplete escape sequence");
                 }
             }
-            '[' => {
-                if *i + 1 < chars.len() && chars[*i + 1] == '^' {
-                    let (class, end_idx) = parse_char_class(chars, *i + 2);
-                    result.push(RE::NegCharClass(class));
-                    *i = end_idx;
-                } else {
-                    let (class, end_idx) = parse_char_class(chars, *i + 1);
-                    result.push(RE::CharClass(class));
-                    *i = end_idx;
+            "-F" | "--fixed-strings" => fixed_strings = true,
+            "-i" | "--ignore-case" => case_insensitive = true,
+            "-w" | "--word-regexp" => whole_words = true,
+            "-v" | "--invert-match" => options.invert = true,
+            "-c" | "--count" => options.count = true,
+            "-n" | "--line-number" => options.line_number = true,
+            "-o" | "--only-matching" => options.only_matching = true,
+            "-l" | "--files-with-matches" => options.files_with_matches = true,
+            "-L" | "--files-without-match" => options.files_without_match = true,
+            "--include" => search_options.include.push(value()),
+            "--exclude" => search_options.exclude.push(value()),
+            "--binary-files" => {
+                search_options.binary_files = match value().as_str() {
+                    "text" => BinaryFiles::Text,
+                    "binary" => BinaryFiles::Binary,
+                    "without-match" => BinaryFiles::WithoutMatch,
+                    _ => usage(),
                 }
             }
-            '(' => {
-                *i += 1; // Move past '('
-                let (group, end_idx) = parse_alternation(chars, *i);
-                result.push(group);
-                *i = end_idx;
+            "-Z" | "--null" => options.null = true,
+            "--json" => options.json = true,
+            "-H" | "--with-filename" => with_filename = Some(true),
+            "-h" | "--no-filename" => with_filename = Some(false),
+            "-r" | "--recursive" => recursive = true,
+            "-R" | "--dereference-recursive" => {
+                recursive = true;
+                search_options.follow_links = true;
             }
-            '?' => {
-                if let Some(last) = result.pop() {
-                    result.push(RE::Question(Box::new(last)));
-                } else {
-                    panic!("Invalid pattern: '?' cannot be the first character");
-                }
+            "-M" | "--multiline" => multiline = true,
+            "--dotall" => dotall = true,
+            "--debug" => debug = true,
+            "--dump-pattern" => dump_pattern = true,
+            "-A" | "--after-context" => options.after_context = value().parse().unwrap_or_else(|_| usage()),
+            "-B" | "--before-context" => options.before_context = value().parse().unwrap_or_else(|_| usage()),
+            #[cfg(feature = "parallel")]
+            "-j" | "--jobs" => jobs = Some(value().parse().unwrap_or_else(|_| usage())),
+            "-m" | "--max-count" => options.max_count = Some(value().parse().unwrap_or_else(|_| usage())),
+            "-C" | "--context" => {
+                let lines = value().parse().unwrap_or_else(|_| usage());
+                options.after_context = lines;
+                options.before_context = lines;
             }
-            '+' => {
-                if let Some(last) = result.pop() {
-                    result.push(RE::Plus(Box::new(last)));
-                } else {
-                    panic!("Invalid pattern: '+' cannot be the first character");
-                }
+            "--color" | "--colour" => color = inline_value.unwrap_or_else(|| "auto".to_string()),
+            // Standard output is flushed at every line break already
+            "--line-buffered" => {}
+            "--help" => {
+                println!("{}\n\n{}", USAGE, HELP);
+                process::exit(0);
+            }
+            "--version" => {
+                println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+                process::exit(0);
+            }
+            // Everything after "--" names an input, even if it looks like an option
+            "--" => files.extend(args.by_ref()),
+            // Anything else that is not an option names an input; a lone '-' is standard input
+            _ if arg == "-" || !arg.starts_with('-') => files.push(arg),
+            _ => {
+                eprintln!("Unknown option: {}", arg);
+                usage();
             }
-            ch => result.push(RE::Char(ch)),
         }
-        *i += 1;
+    }
+    // A pattern file may legitimately be empty, in which case nothing matches
+    if patterns.is_empty() && !pattern_file {
+        usage();
+    }
+    // Counts and file names have no matches to describe
+    if options.json && (options.count || options.files_with_matches || options.files_without_match) {
+        eprintln!("--json cannot be combined with -c, -l or -L");
+        process::exit(2);
     }
 
-    result
-}
-
-fn parse_alternation(chars: &[char], start: usize) -> (RE, usize) {
-    let mut i = start;
-    let left_side = parse_sequence(chars, &mut i);
-    if i < chars.len() && chars[i] == '|' {
-        i += 1; // Move past '|'
-        let right_side = parse_sequence(chars, &mut i);
-        if i < chars.len() && chars[i] == ')' {
-            // Wrap the alternation in a group directly
-            return (
-                RE::Group(vec![RE::Alternation(left_side, right_side)]),
-                i,
-            );
+    let mut engines = Vec::new();
+    for pattern in &patterns {
+        let escaped;
+        let pattern = if fixed_strings {
+            escaped = RegexEngine::escape(pattern);
+            &escaped
         } else {
-            panic!("Unmatched parenthesis or incomplete alternation");
+            pattern
+        };
+        let engine = RegexBuilder::new(pattern)
+            .case_insensitive(case_insensitive)
+            .multiline(multiline)
+            .dot_matches_newline(dotall)
+            .whole_words(whole_words)
+            .depth_limit(DEPTH_LIMIT)
+            .build();
+        // An invalid pattern is an error rather than a failed match, so it gets its own exit code
+        match engine {
+            Ok(engine) => engines.push(engine),
+            Err(err) => {
+                eprintln!("Invalid pattern: {}", err);
+                process::exit(2);
+            }
         }
-    } else if i < chars.len() && chars[i] == ')' {
-        return (RE::Group(left_side), i);
-    } else {
-        panic!("Unmatched parenthesis or invalid alternation syntax");
     }
-}
-
+    // The graphs are the output here, so they go to stdout and nothing is searched
+    if dump_pattern {
+        for engine in &engines {
+            print!("{}", engine.to_dot());
+        }
+        process::exit(0);
+    }
+    // Diagnostics go to stderr so they never mix with the program's regular output
+    if debug {
+        for engine in &engines {
+            eprintln!("pattern to match: {:?}", engine);
+        }
+    }
+    let engines = RegexSet::from(engines);
 
-fn parse_char_class(chars: &[char], start: usize) -> (Vec<char>, usize) {
-    let mut class = Vec::new();
-    let mut i = start;
-
-    while i < chars.len() {
-        if chars[i] == ']' {
-            return (class, i);
-        } else if i + 2 < chars.len() && chars[i + 1] == '-' && chars[i + 2] != ']' {
-            // Handle range like a-z
-            let start = chars[i];
-            let end = chars[i + 2];
-            if start <= end {
-                for c in start..=end {
-                    class.push(c);
-                }
+    let mut had_error = false;
+    if recursive {
+        // Searching recursively without naming anything searches the current directory
+        if files.is_empty() {
+            files.push(".".to_string());
+        }
+        let mut found = Vec::new();
+        for file in &files {
+            if file == "-" {
+                found.push(file.clone());
+            } else {
+                had_error |= !walk(Path::new(file), &search_options, &mut found);
             }
-            i += 2;
-        } else {
-            class.push(chars[i]);
         }
-        i += 1;
+        files = found;
+    } else if files.is_empty() {
+        files.push("-".to_string());
     }
-
-    panic!("Unterminated character class");
-}
-
-// Usage: echo <input_text> | your_program.sh -E <pattern>
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 3 || args[1] != "-E" {
-        eprintln!("Usage: your_program -E <pattern>");
-        process::exit(1);
+    options.with_filename = with_filename.unwrap_or(recursive || files.len() > 1);
+    // Escape sequences only make sense on a terminal unless they are asked for explicitly
+    let use_color = match color.as_str() {
+        "always" => true,
+        "never" => false,
+        "auto" => io::stdout().is_terminal(),
+        _ => usage(),
+    };
+    if use_color {
+        options.color = Some(match_color());
     }
 
-    let pattern_str = &args[2];
-    let mut input = String::new();
-    std::io::stdin().read_line(&mut input).expect("Failed to read input");
-    let input = input.trim();
+    let binary_files = search_options.binary_files;
+    #[cfg(feature = "parallel")]
+    let (success, failed) = match jobs {
+        Some(jobs) if jobs != 1 => {
+            // No number asks for one job per CPU
+            let jobs = if jobs == 0 {
+                std::thread::available_parallelism().map_or(1, |jobs| jobs.get())
+            } else {
+                jobs
+            };
+            search_parallel(&engines, &files, &options, binary_files, jobs)
+        }
+        _ => search_all(&engines, &files, &options, binary_files),
+    };
+    #[cfg(not(feature = "parallel"))]
+    let (success, failed) = search_all(&engines, &files, &options, binary_files);
+    had_error |= failed;
 
-    let engine = RegexEngine::new(pattern_str);
-    if engine.match_text(input) {
+    // A file that could not be read is an error, whatever the other files held
+    if had_error {
+        process::exit(2);
+    }
+    if success {
         process::exit(0);
     } else {
         process::exit(1);
@@ -0,0 +1,114 @@
+// Tables for \p{Script=..} and \p{Block=..}. Blocks are exact. Scripts list the main ranges of
+// each script rather than every assigned code point, which is enough to tell scripts apart in
+// running text without carrying the whole Unicode database.
+
+use alloc::string::String;
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct NamedRanges {
+    pub(crate) name: &'static str,
+    ranges: &'static [(char, char)],
+}
+
+impl NamedRanges {
+    pub(crate) fn contains(&self, c: char) -> bool {
+        self.ranges.iter().any(|&(start, end)| (start..=end).contains(&c))
+    }
+}
+
+// Looks up a script by name. Case, spaces, '_' and '-' are ignored, so "Latin" and "latin" are
+// the same script.
+pub(crate) fn script(name: &str) -> Option<&'static NamedRanges> {
+    lookup(SCRIPTS, name)
+}
+
+// Looks up a block by name, ignoring the same things as script, so "Basic_Latin" and
+// "BasicLatin" are the same block.
+pub(crate) fn block(name: &str) -> Option<&'static NamedRanges> {
+    lookup(BLOCKS, name)
+}
+
+fn lookup(table: &'static [NamedRanges], name: &str) -> Option<&'static NamedRanges> {
+    let normalize = |name: &str| -> String {
+        name.chars().filter(|c| !matches!(c, ' ' | '_' | '-')).map(|c| c.to_ascii_lowercase()).collect()
+    };
+    let name = normalize(name);
+    table.iter().find(|entry| normalize(entry.name) == name)
+}
+
+macro_rules! table {
+    ($($name:literal => [$($start:literal ..= $end:literal),* $(,)?]),* $(,)?) => {
+        &[$(NamedRanges { name: $name, ranges: &[$(($start, $end)),*] }),*]
+    };
+}
+
+static SCRIPTS: &[NamedRanges] = table![
+    "Latin" => [
+        'A'..='Z', 'a'..='z', '\u{AA}'..='\u{AA}', '\u{BA}'..='\u{BA}', '\u{C0}'..='\u{D6}',
+        '\u{D8}'..='\u{F6}', '\u{F8}'..='\u{24F}', '\u{1E00}'..='\u{1EFF}', '\u{2C60}'..='\u{2C7F}',
+        '\u{A720}'..='\u{A7FF}', '\u{FF21}'..='\u{FF3A}', '\u{FF41}'..='\u{FF5A}',
+    ],
+    "Greek" => [
+        '\u{370}'..='\u{373}', '\u{375}'..='\u{377}', '\u{37A}'..='\u{37D}', '\u{37F}'..='\u{37F}',
+        '\u{384}'..='\u{384}', '\u{386}'..='\u{386}', '\u{388}'..='\u{3E1}', '\u{3F0}'..='\u{3FF}',
+        '\u{1F00}'..='\u{1FFF}',
+    ],
+    "Cyrillic" => [
+        '\u{400}'..='\u{52F}', '\u{1C80}'..='\u{1C8F}', '\u{2DE0}'..='\u{2DFF}', '\u{A640}'..='\u{A69F}',
+    ],
+    "Armenian" => ['\u{531}'..='\u{58F}', '\u{FB13}'..='\u{FB17}'],
+    "Hebrew" => ['\u{591}'..='\u{5F4}', '\u{FB1D}'..='\u{FB4F}'],
+    "Arabic" => [
+        '\u{600}'..='\u{604}', '\u{606}'..='\u{60B}', '\u{60D}'..='\u{61A}', '\u{61C}'..='\u{61E}',
+        '\u{620}'..='\u{63F}', '\u{641}'..='\u{64A}', '\u{656}'..='\u{66F}', '\u{671}'..='\u{6DC}',
+        '\u{6DE}'..='\u{6FF}', '\u{750}'..='\u{77F}', '\u{8A0}'..='\u{8FF}', '\u{FB50}'..='\u{FDFF}',
+        '\u{FE70}'..='\u{FEFC}',
+    ],
+    "Devanagari" => ['\u{900}'..='\u{950}', '\u{955}'..='\u{963}', '\u{966}'..='\u{97F}', '\u{A8E0}'..='\u{A8FF}'],
+    "Thai" => ['\u{E01}'..='\u{E3A}', '\u{E40}'..='\u{E5B}'],
+    "Hangul" => [
+        '\u{1100}'..='\u{11FF}', '\u{3131}'..='\u{318E}', '\u{A960}'..='\u{A97F}', '\u{AC00}'..='\u{D7A3}',
+        '\u{D7B0}'..='\u{D7FF}', '\u{FFA0}'..='\u{FFDC}',
+    ],
+    "Hiragana" => ['\u{3041}'..='\u{3096}', '\u{309D}'..='\u{309F}'],
+    "Katakana" => [
+        '\u{30A1}'..='\u{30FA}', '\u{30FD}'..='\u{30FF}', '\u{31F0}'..='\u{31FF}', '\u{FF66}'..='\u{FF6F}',
+        '\u{FF71}'..='\u{FF9D}',
+    ],
+    "Han" => [
+        '\u{2E80}'..='\u{2E99}', '\u{2E9B}'..='\u{2EF3}', '\u{2F00}'..='\u{2FD5}', '\u{3005}'..='\u{3005}',
+        '\u{3007}'..='\u{3007}', '\u{3021}'..='\u{3029}', '\u{3038}'..='\u{303B}', '\u{3400}'..='\u{4DBF}',
+        '\u{4E00}'..='\u{9FFF}', '\u{F900}'..='\u{FAFF}', '\u{20000}'..='\u{2A6DF}', '\u{2A700}'..='\u{2EBEF}',
+        '\u{30000}'..='\u{3134F}',
+    ],
+];
+
+static BLOCKS: &[NamedRanges] = table![
+    "BasicLatin" => ['\u{0}'..='\u{7F}'],
+    "Latin1Supplement" => ['\u{80}'..='\u{FF}'],
+    "LatinExtendedA" => ['\u{100}'..='\u{17F}'],
+    "LatinExtendedB" => ['\u{180}'..='\u{24F}'],
+    "IPAExtensions" => ['\u{250}'..='\u{2AF}'],
+    "GreekAndCoptic" => ['\u{370}'..='\u{3FF}'],
+    "Cyrillic" => ['\u{400}'..='\u{4FF}'],
+    "Armenian" => ['\u{530}'..='\u{58F}'],
+    "Hebrew" => ['\u{590}'..='\u{5FF}'],
+    "Arabic" => ['\u{600}'..='\u{6FF}'],
+    "Devanagari" => ['\u{900}'..='\u{97F}'],
+    "Thai" => ['\u{E00}'..='\u{E7F}'],
+    "HangulJamo" => ['\u{1100}'..='\u{11FF}'],
+    "LatinExtendedAdditional" => ['\u{1E00}'..='\u{1EFF}'],
+    "GreekExtended" => ['\u{1F00}'..='\u{1FFF}'],
+    "GeneralPunctuation" => ['\u{2000}'..='\u{206F}'],
+    "CurrencySymbols" => ['\u{20A0}'..='\u{20CF}'],
+    "Arrows" => ['\u{2190}'..='\u{21FF}'],
+    "MathematicalOperators" => ['\u{2200}'..='\u{22FF}'],
+    "BoxDrawing" => ['\u{2500}'..='\u{257F}'],
+    "CJKSymbolsAndPunctuation" => ['\u{3000}'..='\u{303F}'],
+    "Hiragana" => ['\u{3040}'..='\u{309F}'],
+    "Katakana" => ['\u{30A0}'..='\u{30FF}'],
+    "CJKUnifiedIdeographs" => ['\u{4E00}'..='\u{9FFF}'],
+    "HangulSyllables" => ['\u{AC00}'..='\u{D7AF}'],
+    "HalfwidthAndFullwidthForms" => ['\u{FF00}'..='\u{FFEF}'],
+    "Emoticons" => ['\u{1F600}'..='\u{1F64F}'],
+];
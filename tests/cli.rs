@@ -0,0 +1,334 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+// Runs the binary with `args`, feeding it `input`, and returns its exit code and stdout
+fn run(args: &[&str], input: &str) -> (i32, String) {
+    let (code, stdout) = run_bytes(args, input.as_bytes());
+    (code, String::from_utf8(stdout).unwrap())
+}
+
+// Like run, for input and output that need not be UTF-8
+fn run_bytes(args: &[&str], input: &[u8]) -> (i32, Vec<u8>) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_codecrafters-grep"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    // The program may exit without reading its input, e.g. on an invalid pattern
+    let _ = child.stdin.take().unwrap().write_all(input);
+    let output = child.wait_with_output().unwrap();
+    (output.status.code().unwrap(), output.stdout)
+}
+
+#[test]
+fn exit_code_reports_match() {
+    assert_eq!(run(&["-E", "d"], "dog\n").0, 0);
+    assert_eq!(run(&["-E", "f"], "dog\n").0, 1);
+    assert_eq!(run(&["-E", "(d"], "dog\n").0, 2);
+}
+
+#[test]
+fn backreferences_print_only_the_line() {
+    assert_eq!(run(&["-E", "(\\w+) \\1"], "hey hey\n"), (0, "hey hey\n".to_string()));
+    // Nothing else reaches stdout, whether or not the backreference matches
+    assert_eq!(run(&["-E", "(a|b)\\1"], "ab\nba\n"), (1, String::new()));
+    assert_eq!(run(&["-c", "-E", "(\\w)\\1"], "aa\nab\n"), (0, "1\n".to_string()));
+
+    // --debug writes the parsed pattern to stderr only
+    let output = Command::new(env!("CARGO_BIN_EXE_codecrafters-grep"))
+        .args(["--debug", "-E", "(\\w+) \\1"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .and_then(|mut child| {
+            child.stdin.take().unwrap().write_all(b"hey hey\n")?;
+            child.wait_with_output()
+        })
+        .unwrap();
+    assert_eq!(output.stdout, b"hey hey\n");
+    assert!(String::from_utf8(output.stderr).unwrap().contains("Backreference"));
+}
+
+#[test]
+fn invert_match() {
+    assert_eq!(run(&["-v", "-E", "f"], "dog\n").0, 0);
+    assert_eq!(run(&["-E", "d", "--invert-match"], "dog\n").0, 1);
+}
+
+#[test]
+fn count_matching_lines() {
+    assert_eq!(run(&["-E", "a", "-c"], "a\nb\na\n"), (0, "2\n".to_string()));
+    assert_eq!(run(&["-c", "-v", "-E", "a"], "a\nb\na\n"), (0, "1\n".to_string()));
+    assert_eq!(run(&["--count", "-E", "x"], "a\nb\n"), (1, "0\n".to_string()));
+}
+
+#[test]
+fn print_selected_lines() {
+    assert_eq!(run(&["-E", "a"], "a1\nb\na2\n"), (0, "a1\na2\n".to_string()));
+    assert_eq!(run(&["-v", "-E", "a"], "a1\nb\na2\n"), (0, "b\n".to_string()));
+}
+
+#[test]
+fn line_numbers() {
+    assert_eq!(run(&["-n", "-E", "a"], "x\ny\na\nz\nba\n"), (0, "3:a\n5:ba\n".to_string()));
+    assert_eq!(run(&["--line-number", "-v", "-E", "a"], "a\nb\n"), (0, "2:b\n".to_string()));
+}
+
+#[test]
+fn only_matching() {
+    assert_eq!(run(&["-o", "-E", "\\d+"], "a1b22\nnone\n333\n"), (0, "1\n22\n333\n".to_string()));
+    assert_eq!(run(&["-o", "-n", "-E", "o+"], "foo\nbar\nboo\n"), (0, "1:oo\n3:oo\n".to_string()));
+    assert_eq!(run(&["-o", "-E", "x*"], "abc\n"), (0, String::new()));
+}
+
+// Writes `contents` to a fresh file in the system temp directory and returns its path
+fn temp_file(name: &str, contents: &str) -> String {
+    let dir = std::env::temp_dir().join(format!("mygrep-cli-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    std::fs::write(&path, contents).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+#[test]
+fn list_files_with_and_without_matches() {
+    let with = temp_file("with.txt", "x\napple\n");
+    let without = temp_file("without.txt", "x\ny\n");
+    let expected = format!("{}\n", with);
+    assert_eq!(run(&["-l", "-E", "app", &with, &without], ""), (0, expected));
+    let expected = format!("{}\n", without);
+    assert_eq!(run(&["-L", "-E", "app", &with, &without], ""), (0, expected));
+    assert_eq!(run(&["-l", "-E", "zzz", &with, &without], ""), (1, String::new()));
+    assert_eq!(run(&["-l", "-E", "a"], "a\n"), (0, "(standard input)\n".to_string()));
+    assert_eq!(run(&["-l", "-E", "a", "/nonexistent/file"], "").0, 2);
+}
+
+#[test]
+fn several_patterns() {
+    let input = "cat\ndog\nbird\n";
+    assert_eq!(run(&["-e", "cat", "-e", "bird"], input), (0, "cat\nbird\n".to_string()));
+    assert_eq!(run(&["-v", "-e", "cat", "-E", "bird"], input), (0, "dog\n".to_string()));
+    assert_eq!(run(&["-o", "-e", "a", "-e", "ca"], "cat bat\n"), (0, "ca\na\n".to_string()));
+
+    let patterns = temp_file("patterns.txt", "do\nir\n");
+    assert_eq!(run(&["-f", &patterns], input), (0, "dog\nbird\n".to_string()));
+    let empty = temp_file("empty-patterns.txt", "");
+    assert_eq!(run(&["-f", &empty], input).0, 1);
+}
+
+#[test]
+fn file_name_prefixes() {
+    let one = temp_file("one.txt", "apple\nberry\n");
+    let two = temp_file("two.txt", "grape\n");
+    let expected = format!("{}:apple\n{}:grape\n", one, two);
+    assert_eq!(run(&["-E", "ap", &one, &two], ""), (0, expected));
+    assert_eq!(run(&["-h", "-E", "ap", &one, &two], ""), (0, "apple\ngrape\n".to_string()));
+    assert_eq!(run(&["-E", "ap", &one], ""), (0, "apple\n".to_string()));
+    let expected = format!("{}:2:berry\n", one);
+    assert_eq!(run(&["-H", "-n", "-E", "rr", &one], ""), (0, expected));
+    let expected = format!("{}:1\n{}:1\n", one, two);
+    assert_eq!(run(&["-c", "-E", "ap", &one, &two], ""), (0, expected));
+
+    // A missing file is reported, but the other files are still searched
+    let expected = format!("{}:apple\n", one);
+    assert_eq!(run(&["-E", "ap", "/nonexistent/file", &one], ""), (2, expected));
+}
+
+#[test]
+fn recursive_search() {
+    let root = std::env::temp_dir().join(format!("mygrep-tree-{}", std::process::id()));
+    std::fs::create_dir_all(root.join("sub")).unwrap();
+    std::fs::write(root.join("a.txt"), "needle\nhay\n").unwrap();
+    std::fs::write(root.join("sub").join("b.txt"), "hay\nneedle here\n").unwrap();
+    std::fs::write(root.join("sub").join("c.bin"), "needle\0").unwrap();
+    let root = root.to_str().unwrap();
+
+    let expected = format!("{root}/a.txt:1:needle\n{root}/sub/b.txt:2:needle here\nBinary file {root}/sub/c.bin matches\n");
+    assert_eq!(run(&["-r", "-n", "-E", "needle", root], ""), (0, expected));
+    let expected = format!("{root}/sub/b.txt\n");
+    assert_eq!(run(&["-r", "-l", "-E", "here", root], ""), (0, expected));
+    assert_eq!(run(&["-r", "-E", "nothing", root], "").0, 1);
+    let expected = format!("{root}/sub/b.txt\n");
+    assert_eq!(run(&["-r", "-l", "--include=*.txt", "--exclude=a*", "-E", "needle", root], ""), (0, expected));
+    let expected = format!("{root}/a.txt\n{root}/sub/b.txt\n");
+    assert_eq!(run(&["-r", "-L", "--include=*.md", "--include=*.txt", "-E", "nothing", root], ""), (0, expected));
+}
+
+#[test]
+fn binary_files() {
+    let file = std::env::temp_dir().join(format!("mygrep-binary-{}", std::process::id()));
+    std::fs::write(&file, b"header\0\xff\nneedle one\nneedle two\n").unwrap();
+    let file = file.to_str().unwrap();
+
+    let expected = format!("Binary file {file} matches\n");
+    assert_eq!(run(&["-E", "needle", file], ""), (0, expected.clone()));
+    assert_eq!(run(&["--binary-files=binary", "-E", "needle", file], ""), (0, expected));
+    assert_eq!(run(&["--binary-files=binary", "-E", "nothing", file], ""), (1, String::new()));
+    // Counts are still printed for binary files, even past invalid UTF-8
+    assert_eq!(run(&["-c", "-E", "needle", file], ""), (0, "2\n".to_string()));
+    assert_eq!(run(&["--binary-files=without-match", "-E", "needle", file], ""), (1, String::new()));
+    // Standard input is checked the same way
+    let expected = "Binary file (standard input) matches\n".to_string();
+    assert_eq!(run(&["-E", "needle"], "a\0needle\n"), (0, expected));
+    assert_eq!(run(&["--binary-files", "text", "-E", "needle"], "a\0needle\n"), (0, "a\0needle\n".to_string()));
+    let text = std::env::temp_dir().join(format!("mygrep-text-{}", std::process::id()));
+    std::fs::write(&text, "nul\0 needle\n").unwrap();
+    let text = text.to_str().unwrap();
+    assert_eq!(run(&["--binary-files=text", "-E", "needle", text], ""), (0, "nul\0 needle\n".to_string()));
+    assert_eq!(run(&["--binary-files=other", "-E", "needle", text], "").0, 2);
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn parallel_search() {
+    let root = std::env::temp_dir().join(format!("mygrep-jobs-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    for i in 0..100 {
+        let contents = if i % 3 == 0 { format!("hay\nneedle {i}\n") } else { "hay\n".to_string() };
+        std::fs::write(root.join(format!("{i:03}.txt")), contents).unwrap();
+    }
+    let root = root.to_str().unwrap();
+
+    let expected: String = (0..100).step_by(3).map(|i| format!("{root}/{i:03}.txt:2:needle {i}\n")).collect();
+    for jobs in ["0", "1", "4"] {
+        assert_eq!(run(&["-r", "-n", "-j", jobs, "-E", "needle", root], ""), (0, expected.clone()));
+    }
+    let expected: String = (0..100).filter(|i| i % 3 != 0).map(|i| format!("{root}/{i:03}.txt\n")).collect();
+    assert_eq!(run(&["-r", "-L", "--jobs=8", "-E", "needle", root], ""), (0, expected));
+    assert_eq!(run(&["-r", "-j3", "-c", "-E", "nothing", root], "").0, 1);
+    // Workers have the stack for searches as deep as the ones made without -j
+    let long = std::env::temp_dir().join(format!("mygrep-jobs-long-{}", std::process::id()));
+    std::fs::write(&long, format!("{}c\n", "ab".repeat(3_000))).unwrap();
+    let long = long.to_str().unwrap();
+    let expected = format!("{root}/000.txt:0\n{long}:1\n");
+    assert_eq!(run(&["-c", "-j", "2", "-e", "(?:ab)+c", &format!("{root}/000.txt"), long], ""), (0, expected));
+    // A missing file is still an error, and the others are still searched
+    let expected = format!("{root}/000.txt:needle 0\n");
+    assert_eq!(run(&["-j", "2", "-E", "needle", "/nonexistent/file", &format!("{root}/000.txt")], ""), (2, expected));
+}
+
+#[test]
+fn long_lines() {
+    let line = format!("{}c\n", "ab".repeat(1_000));
+    assert_eq!(run(&["-E", "^(a|b)+c$"], &line), (0, line.clone()));
+    let literal = "needle".repeat(300);
+    assert_eq!(run(&["-c", "-E", &literal], &format!("{}\n", literal)), (0, "1\n".to_string()));
+    // A search too deep even for the command line is an error, not a line that does not match
+    assert_eq!(run(&["-E", "^(a|b)+c$"], &format!("{}c\n", "ab".repeat(50_000))).0, 2);
+}
+
+#[test]
+fn lines_that_are_not_utf8() {
+    let file = std::env::temp_dir().join(format!("mygrep-latin1-{}", std::process::id()));
+    std::fs::write(&file, b"caf\xe9 au lait\nplain tea\nna\xefve caf\xe9\n").unwrap();
+    let file = file.to_str().unwrap();
+
+    // Lines are printed as they were read, and a bad byte does not stop the search
+    assert_eq!(run_bytes(&["-E", "caf", file], b""), (0, b"caf\xe9 au lait\nna\xefve caf\xe9\n".to_vec()));
+    assert_eq!(run(&["-n", "-E", "tea", file], ""), (0, "2:plain tea\n".to_string()));
+    assert_eq!(run(&["-c", "-E", "caf.$", file], ""), (0, "1\n".to_string()));
+    assert_eq!(run_bytes(&["-B", "1", "-E", "tea"], b"\xff\ntea\n"), (0, b"-\xff\ntea\n".to_vec()));
+}
+
+#[test]
+fn whole_words() {
+    assert_eq!(run(&["-w", "-E", "cat"], "cat\nconcatenate\ncats\na cat.\n"), (0, "cat\na cat.\n".to_string()));
+    // A longer alternative is tried when the first one does not end on a word boundary
+    assert_eq!(run(&["-w", "-o", "-E", "cat|cats"], "cats\n"), (0, "cats\n".to_string()));
+}
+
+#[test]
+fn color_output() {
+    assert_eq!(run(&["--color=always", "-E", "b+"], "abbc\n"), (0, "a\x1b[1;31mbb\x1b[0mc\n".to_string()));
+    assert_eq!(run(&["--color=always", "-o", "-E", "b"], "ab\n"), (0, "\x1b[1;31mb\x1b[0m\n".to_string()));
+    assert_eq!(run(&["--color=never", "-E", "b"], "ab\n"), (0, "ab\n".to_string()));
+    // Standard output is a pipe here, so auto mode leaves the output alone
+    assert_eq!(run(&["--color", "-E", "b"], "ab\n"), (0, "ab\n".to_string()));
+}
+
+#[test]
+fn context_lines() {
+    let input = "1\n2\nx\n4\n5\n6\n7\nx\n9\n";
+    assert_eq!(run(&["-A", "1", "-E", "x"], input), (0, "x\n+4\n--\nx\n+9\n".to_string()));
+    assert_eq!(run(&["-B", "2", "-n", "-E", "x"], input), (0, "1-1\n2-2\n3:x\n--\n6-6\n7-7\n8:x\n".to_string()));
+    assert_eq!(run(&["-A", "1", "-n", "-E", "x"], input), (0, "3:x\n4+4\n--\n8:x\n9+9\n".to_string()));
+    // Overlapping windows are merged rather than separated
+    assert_eq!(run(&["-C", "2", "-E", "x"], input), (0, "-1\n-2\nx\n+4\n+5\n-6\n-7\nx\n+9\n".to_string()));
+    assert_eq!(run(&["-C", "1", "-E", "x"], "a\nx\nx\nb\nc\n"), (0, "-a\nx\nx\n+b\n".to_string()));
+}
+
+#[test]
+fn long_and_bundled_options() {
+    assert_eq!(run(&["--invert-match", "--count", "--regexp=a"], "a\nb\nc\n"), (0, "2\n".to_string()));
+    assert_eq!(run(&["-vn", "-Ea"], "a\nb\n"), (0, "2:b\n".to_string()));
+    assert_eq!(run(&["-A1", "--extended-regexp", "x"], "x\ny\nz\n"), (0, "x\n+y\n".to_string()));
+    assert_eq!(run(&["--null", "-H", "-E", "a"], "a\n"), (0, "(standard input)\0a\n".to_string()));
+    // A pattern given after an option that takes one is never mistaken for options
+    assert_eq!(run(&["-e", "-foo"], "a-foo\nfoo\n"), (0, "a-foo\n".to_string()));
+    assert_eq!(run(&["-E", "-e", "-12"], "x-12\n12\n"), (0, "x-12\n".to_string()));
+    assert_eq!(run(&["-E", "-\\d+"], "a-3\n3\n"), (0, "a-3\n".to_string()));
+    assert_eq!(run(&["-cA", "-1", "-E", "a"], "a\n").0, 2);
+}
+
+#[test]
+fn help_version_and_unknown_options() {
+    let (code, help) = run(&["--help"], "");
+    assert_eq!(code, 0);
+    assert!(help.contains("--ignore-case"));
+    let (code, version) = run(&["--version"], "");
+    assert_eq!((code, version), (0, format!("codecrafters-grep {}\n", env!("CARGO_PKG_VERSION"))));
+    assert_eq!(run(&["--frobnicate", "-E", "a"], "a\n"), (2, String::new()));
+}
+
+#[test]
+fn end_anchor_with_crlf_lines() {
+    assert_eq!(run(&["-E", "b$"], "ab\r\ncd\n"), (0, "ab\n".to_string()));
+}
+
+#[test]
+fn fixed_strings() {
+    assert_eq!(run(&["-F", "-e", "a.b"], "axb\na.b\n"), (0, "a.b\n".to_string()));
+    assert_eq!(run(&["--fixed-strings", "-e", "(x)*"], "xx\n(x)*\n"), (0, "(x)*\n".to_string()));
+    assert_eq!(run(&["-F", "-e", r"\E+"], "E\n\\E+\n"), (0, "\\E+\n".to_string()));
+    assert_eq!(run(&["-Fio", "-e", "A.B"], "xa.by\n"), (0, "a.b\n".to_string()));
+}
+
+#[test]
+fn max_count() {
+    assert_eq!(run(&["-m", "2", "-E", "a"], "a1\nb\na2\na3\n"), (0, "a1\na2\n".to_string()));
+    assert_eq!(run(&["--max-count=2", "-c", "-E", "a"], "a\na\na\n"), (0, "2\n".to_string()));
+    assert_eq!(run(&["-m1", "-A1", "-E", "a"], "a1\na2\nb\n"), (0, "a1\n+a2\n".to_string()));
+    assert_eq!(run(&["-m", "0", "-E", "a"], "a\n"), (1, String::new()));
+}
+
+#[test]
+fn json_output() {
+    let (code, output) = run(&["--json", "-E", r"(\d)(x)?"], "a1 2\nnone\n\"3\"\n");
+    assert_eq!(code, 0);
+    assert_eq!(
+        output,
+        concat!(
+            r#"{"file":"(standard input)","line":1,"match":"1","start":1,"end":2,"captures":["1",null]}"#,
+            "\n",
+            r#"{"file":"(standard input)","line":1,"match":"2","start":3,"end":4,"captures":["2",null]}"#,
+            "\n",
+            r#"{"file":"(standard input)","line":3,"match":"3","start":1,"end":2,"captures":["3",null]}"#,
+            "\n",
+        )
+    );
+    let (_, output) = run(&["--json", "-E", r#"a\\"#], "a\\\n");
+    assert!(output.contains(r#""match":"a\\""#));
+    assert_eq!(run(&["--json", "-c", "-E", "a"], "a\n"), (2, String::new()));
+    assert_eq!(run(&["--json", "-l", "-E", "a"], "a\n"), (2, String::new()));
+}
+
+#[test]
+fn dump_pattern() {
+    let (code, output) = run(&["--dump-pattern", "-E", "a+"], "a\n");
+    assert_eq!(code, 0);
+    assert!(output.starts_with("digraph pattern {"));
+    assert!(output.contains(r#"[label="Repeat +"]"#));
+}
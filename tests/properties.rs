@@ -0,0 +1,44 @@
+use mygrep::RegexEngine;
+use proptest::prelude::*;
+
+// Small patterns over a few letters and operators. Most of them are valid; the rest are skipped.
+fn patterns() -> impl Strategy<Value = RegexEngine> {
+    "[abé.*+?|()\\[\\]^$\\\\dw]{0,8}".prop_filter_map("invalid pattern", |pattern| RegexEngine::new(&pattern).ok())
+}
+
+// Texts over the same letters, so that the patterns have something to match
+fn texts() -> impl Strategy<Value = String> {
+    "[abé 1\n]{0,12}"
+}
+
+proptest! {
+    #[test]
+    fn escaped_text_matches_itself(text in any::<String>()) {
+        let engine = RegexEngine::new(&RegexEngine::escape(&text)).unwrap();
+        prop_assert!(engine.match_text(&text));
+    }
+
+    #[test]
+    fn matching_is_repeatable(engine in patterns(), text in texts()) {
+        prop_assert_eq!(engine.match_text(&text), engine.match_text(&text));
+        prop_assert_eq!(engine.find(&text), engine.find(&text));
+    }
+
+    #[test]
+    fn matches_do_not_overlap(engine in patterns(), text in texts()) {
+        let mut last_end = 0;
+        for m in engine.find_iter(&text) {
+            prop_assert!(m.start() >= last_end);
+            prop_assert!(m.start() <= m.end());
+            prop_assert!(text.is_char_boundary(m.start()) && text.is_char_boundary(m.end()));
+            prop_assert_eq!(&text[m.start()..m.end()], m.as_str());
+            last_end = m.end();
+        }
+    }
+
+    #[test]
+    fn replacing_every_character_with_itself(text in any::<String>()) {
+        let engine = RegexEngine::new("(?s).").unwrap();
+        prop_assert_eq!(engine.replace_all(&text, "$0"), text);
+    }
+}
@@ -0,0 +1,962 @@
+use mygrep::{grep_lines, validate_pattern, Error, Flags, GrepOptions, MatchCache, RegexBuilder, RegexEngine, RegexSet};
+
+fn matches(pattern: &str, text: &str) -> bool {
+    RegexEngine::new(pattern).unwrap().match_text(text)
+}
+
+fn found<'t>(pattern: &str, text: &'t str) -> Option<&'t str> {
+    RegexEngine::new(pattern).unwrap().find(text).map(|m| m.as_str())
+}
+
+#[test]
+fn literals_and_classes() {
+    assert!(matches("d", "dog"));
+    assert!(!matches("f", "dog"));
+    assert!(matches("\\d apple", "1 apple"));
+    assert!(matches("[^xyz]", "apple"));
+    assert!(!matches("[abc]", "xyz"));
+    // Characters outside ASCII are checked against the class items one by one
+    assert!(matches("^[à-ÿ]$", "é"));
+    assert!(matches("^[[:alpha:]]$", "é"));
+    assert!(!matches("[^\\w]", "é"));
+}
+
+#[test]
+fn anchors() {
+    assert!(matches("^log", "logs"));
+    assert!(!matches("^log", "slog"));
+    assert!(matches("dog$", "hotdog"));
+    assert!(!matches("dog$", "dogs"));
+}
+
+#[test]
+fn quantifiers() {
+    assert!(matches("ca+ts", "caaats"));
+    assert!(matches("dogs?", "dog"));
+    assert!(matches("(cat|dog)+$", "catdogcat"));
+    assert!(!matches("^(?:ab){3}$", "abab"));
+}
+
+#[test]
+fn groups_and_backreferences() {
+    assert!(matches("(cat|dog) and \\1", "dog and dog"));
+    assert!(!matches("(cat|dog) and \\1", "dog and cat"));
+    assert!(matches("((a)(b))\\3\\2", "abba"));
+    assert!(!matches("a(b)", "axxb"));
+}
+
+#[test]
+fn builder_flags() {
+    let engine = RegexBuilder::new("^hello$").case_insensitive(true).build().unwrap();
+    assert!(engine.match_text("HeLLo"));
+    let engine = RegexBuilder::new("^b$").multiline(true).build().unwrap();
+    assert!(engine.match_text("a\nb\nc"));
+
+    let flags = Flags { dotall: true, ..Flags::default() };
+    let engine = RegexBuilder::new("a.b").flags(flags).build().unwrap();
+    assert_eq!(engine.flags(), flags);
+    assert!(engine.match_text("a\nb"));
+    assert!(!RegexEngine::new("a.b").unwrap().match_text("a\nb"));
+}
+
+#[test]
+fn invalid_patterns() {
+    assert_eq!(RegexEngine::new("(ab").unwrap_err(), Error::UnmatchedParen);
+    assert_eq!(RegexEngine::new("[ab").unwrap_err(), Error::UnterminatedClass);
+    assert_eq!(RegexEngine::new("*a").unwrap_err(), Error::EmptyQuantifier('*'));
+    assert_eq!(RegexEngine::new("a{3,2}").unwrap_err(), Error::InvalidRepeatCount(3, 2));
+    assert_eq!(RegexEngine::new("a\\").unwrap_err(), Error::TrailingBackslash);
+    assert_eq!(RegexEngine::new("[z-a]").unwrap_err(), Error::InvalidClassRange);
+    assert_eq!(RegexEngine::new("[a-\\d]").unwrap_err(), Error::InvalidClassRange);
+    assert_eq!(RegexEngine::new(r"(?P<x>a)\k<nope>").unwrap_err(), Error::InvalidNamedBackreference("nope".to_string()));
+    assert_eq!(RegexEngine::new(r"(?P<x>a)|(?P<x>b)").unwrap_err(), Error::DuplicateGroupName("x".to_string()));
+    assert_eq!(RegexEngine::new(r"(?P<x>a)(?P<y>b)(?:(?P<y>c))").unwrap_err(), Error::DuplicateGroupName("y".to_string()));
+    // A named backreference may still refer to a group later in the pattern
+    assert!(RegexEngine::new(r"(?:\k<x>|(?P<x>a))+").is_ok());
+
+    assert_eq!(validate_pattern("(a|b)+c"), Ok(()));
+    assert_eq!(validate_pattern("(a|b"), Err(Error::UnmatchedParen));
+    assert_eq!(RegexEngine::new("(a|b)+c").unwrap().as_str(), "(a|b)+c");
+}
+
+#[test]
+fn find_reports_match_position() {
+    let engine = RegexEngine::new("\\d+").unwrap();
+    let m = engine.find("abc 123 def").unwrap();
+    assert_eq!((m.start(), m.end(), m.as_str()), (4, 7, "123"));
+    assert!(engine.find("no digits").is_none());
+
+    let m = RegexEngine::new("(?<=é)x+").unwrap().find("éxx").unwrap();
+    assert_eq!((m.start(), m.end(), m.as_str()), (2, 4, "xx"));
+    let m = RegexEngine::new("a*").unwrap().find("bbb").unwrap();
+    assert_eq!((m.start(), m.end()), (0, 0));
+}
+
+#[test]
+fn find_iter_yields_every_match() {
+    let engine = RegexEngine::new("\\d+").unwrap();
+    let found: Vec<_> = engine.find_iter("1 22 333").map(|m| m.as_str()).collect();
+    assert_eq!(found, ["1", "22", "333"]);
+
+    // Adjacent matches
+    let found: Vec<_> = RegexEngine::new("ab").unwrap().find_iter("ababab").map(|m| m.start()).collect();
+    assert_eq!(found, [0, 2, 4]);
+    assert_eq!(RegexEngine::new("^a").unwrap().find_iter("aaa").count(), 1);
+
+    // Zero-length matches, including one at the very end
+    let found: Vec<_> = RegexEngine::new("x*").unwrap().find_iter("axxé").map(|m| (m.start(), m.end())).collect();
+    assert_eq!(found, [(0, 0), (1, 3), (3, 3), (5, 5)]);
+}
+
+#[test]
+fn captures_by_index_and_name() {
+    let engine = RegexEngine::new("(?P<key>\\w+)=(\\d+)?").unwrap();
+    let caps = engine.captures("set width=80").unwrap();
+    assert_eq!(&caps[0], "width=80");
+    assert_eq!(&caps[1], "width");
+    assert_eq!(caps.get(2), Some("80"));
+    assert_eq!(caps.name("key"), Some("width"));
+    assert_eq!(caps.get(3), None);
+
+    let caps = engine.captures("flag=").unwrap();
+    assert_eq!(caps.get(2), None);
+    assert!(engine.captures("nothing here").is_none());
+
+    let keys: Vec<_> = engine.captures_iter("a=1 b=2").map(|caps| caps[1].to_string()).collect();
+    assert_eq!(keys, ["a", "b"]);
+}
+
+#[test]
+fn replace_with_captures() {
+    let engine = RegexEngine::new("(\\w+)@(?P<host>\\w+)").unwrap();
+    assert_eq!(engine.replace("mail a@x or b@y", "$2:$1"), "mail x:a or b@y");
+    assert_eq!(engine.replace_all("mail a@x or b@y", "${host}/$1"), "mail x/a or y/b");
+    assert_eq!(engine.replace_all("a@x", "[$0] $$1 ${1}0 ${nope}"), "[a@x] $1 a0 ");
+    assert_eq!(engine.replace_all("a@x", "${1"), "${1");
+    assert_eq!(engine.replace_all("no match", "$1"), "no match");
+
+    let upper = engine.replace_all_with("a@x b@y", |caps| caps[1].to_uppercase());
+    assert_eq!(upper, "A B");
+    assert_eq!(RegexEngine::new("x*").unwrap().replace_all("abc", "-"), "-a-b-c-");
+}
+
+#[test]
+fn split_on_matches() {
+    let engine = RegexEngine::new(",+").unwrap();
+    assert_eq!(engine.split("one,two,,three").collect::<Vec<_>>(), ["one", "two", "three"]);
+    assert_eq!(engine.split(",a,").collect::<Vec<_>>(), ["", "a", ""]);
+    assert_eq!(engine.split("").collect::<Vec<_>>(), [""]);
+    assert_eq!(engine.splitn("a,b,c", 2).collect::<Vec<_>>(), ["a", "b,c"]);
+    assert_eq!(engine.splitn("a,b,c", 5).collect::<Vec<_>>(), ["a", "b", "c"]);
+    assert_eq!(engine.splitn("a,b,c", 0).count(), 0);
+
+    let empty = RegexEngine::new("x*").unwrap();
+    assert_eq!(empty.split("ab").collect::<Vec<_>>(), ["", "a", "b", ""]);
+}
+
+#[test]
+fn display_round_trips() {
+    assert_eq!(RegexEngine::new("a.b+c{2,}?").unwrap().to_string(), "a.b+c{2,}?");
+    assert_eq!(RegexEngine::new("[^a-z\\d[:punct:]]").unwrap().to_string(), "[^a-z\\d[:punct:]]");
+    assert_eq!(RegexEngine::new("(?P<x>a|b)\\k<x>").unwrap().to_string(), "(?P<x>a|b)\\k<x>");
+    // Counted quantifiers that have a shorthand are written with it
+    assert_eq!(RegexEngine::new("a{0,1}b{1,}?c{0,}+").unwrap().to_string(), "a?b+?c*+");
+    assert_eq!(RegexEngine::new("\\x2E[\\x5D]\\\\").unwrap().to_string(), "\\.[\\]]\\\\");
+    // A quantified digit after a backreference cannot run into its group number
+    assert_eq!(RegexEngine::new("(a)\\1(?:0)+").unwrap().to_string(), "(a)(?:\\1)0+");
+
+    let patterns = [
+        "cat|dog",
+        "(a(?:b|c)*)\\1$",
+        "(?i)ab(?-i:c)",
+        "(?>a+)(?=b)(?<!x)\\b\\w\\S",
+        "\\x2E\\x7C a\\#",
+        "[\\x5D\\x2D^]+",
+        "(?:a*)?",
+        "\\t\\u00E9*+",
+        "(a)\\1(?:0)+",
+        "(a)\\1(?:0){2}",
+    ];
+    for pattern in patterns {
+        let once = RegexEngine::new(pattern).unwrap().to_string();
+        let twice = RegexEngine::new(&once).unwrap().to_string();
+        assert_eq!(once, twice, "{} did not round-trip", pattern);
+    }
+}
+
+#[test]
+fn parse_from_str() {
+    let engine: RegexEngine = "\\d{4}".parse().unwrap();
+    assert!(engine.match_text("in 2024"));
+    assert_eq!("(a".parse::<RegexEngine>().unwrap_err(), Error::UnmatchedParen);
+    let err: Box<dyn std::error::Error> = "[a".parse::<RegexEngine>().unwrap_err().into();
+    assert_eq!(err.to_string(), "unterminated character class");
+}
+
+#[test]
+fn regex_set_matches() {
+    let set = RegexSet::new(&["cat", "dog", r"\d+"]).unwrap();
+    assert_eq!(set.matches("a dog"), vec![1]);
+    assert_eq!(set.matches("cat and dog"), vec![0, 1]);
+    assert!(!set.is_match("bird"));
+    // Overlapping patterns all report a match
+    let set = RegexSet::new(&["ab", "b+", "a.c"]).unwrap();
+    assert_eq!(set.matches("abc"), vec![0, 1, 2]);
+    let empty = RegexSet::new(&[]).unwrap();
+    assert!(empty.is_empty());
+    assert!(!empty.is_match("anything"));
+    assert_eq!(RegexSet::new(&["a", "("]).unwrap_err(), Error::UnmatchedParen);
+}
+
+#[test]
+fn backtrack_limit() {
+    let text = "a".repeat(40);
+    let engine = RegexBuilder::new("(a+)+b").memoize(false).build().unwrap();
+    assert_eq!(engine.try_find(&text), Err(Error::BacktrackLimitExceeded));
+    assert!(!engine.match_text(&text));
+    // The limit does not get in the way of ordinary searches
+    assert_eq!(engine.try_find("aab").unwrap().map(|m| m.as_str()), Some("aab"));
+    let engine = RegexBuilder::new("a*b").backtrack_limit(10).build().unwrap();
+    assert_eq!(engine.try_find(&text), Err(Error::BacktrackLimitExceeded));
+    assert_eq!(engine.try_find("b").unwrap().map(|m| m.as_str()), Some("b"));
+}
+
+#[test]
+fn memoization_avoids_exponential_backtracking() {
+    let text = "a".repeat(40);
+    for pattern in ["(a+)+b", "(a*)*b"] {
+        let engine = RegexEngine::new(pattern).unwrap();
+        assert_eq!(engine.try_find(&text), Ok(None), "{}", pattern);
+    }
+    // Backreferences still see the captures, which the memo does not record
+    let engine = RegexEngine::new(r"(a+)+\1b").unwrap();
+    assert_eq!(engine.find("aaab").map(|m| m.as_str()), Some("aaab"));
+}
+
+#[test]
+fn depth_limit() {
+    let nested = format!("{}a{}", "(".repeat(10_000), ")".repeat(10_000));
+    assert_eq!(RegexEngine::new(&nested).unwrap_err(), Error::StackDepthExceeded);
+    assert_eq!(validate_pattern(&nested), Err(Error::StackDepthExceeded));
+    let engine = RegexBuilder::new("(?:ab)+c").depth_limit(50).build().unwrap();
+    assert_eq!(engine.try_find(&"ab".repeat(100)), Err(Error::StackDepthExceeded));
+    assert_eq!(engine.try_find("ababc").unwrap().map(|m| m.as_str()), Some("ababc"));
+    // Hitting the limit inside a negative lookahead is an error, not a lookahead that failed
+    let engine = RegexBuilder::new("^(?!(?:ab)+c)").depth_limit(50).build().unwrap();
+    let text = "ab".repeat(30) + "c";
+    assert_eq!(engine.try_find(&text), Err(Error::StackDepthExceeded));
+    assert_eq!(RegexEngine::new("^(?!(?:ab)+c)").unwrap().try_find(&text), Ok(None));
+    let engine = RegexBuilder::new("^(?!(?:ab)+c)").backtrack_limit(20).build().unwrap();
+    assert_eq!(engine.try_find(&text), Err(Error::BacktrackLimitExceeded));
+    // Single-character quantifiers do not recurse per character
+    let engine = RegexBuilder::new("a.*b").depth_limit(50).build().unwrap();
+    assert!(engine.match_text(&format!("a{}b", "x".repeat(10_000))));
+}
+
+#[test]
+fn long_literals_fit_the_default_depth_limit() {
+    // Literal text is matched without recursing, so its length does not count against the limit
+    let literal = "ab".repeat(5_000);
+    let engine = RegexEngine::new(&format!("x{}y", literal)).unwrap();
+    let text = format!("xx{}y", literal);
+    assert_eq!(engine.try_find(&text).unwrap().map(|m| m.start()), Some(1));
+    let engine = RegexEngine::new(&format!("(?i)^{}[0-9]$", literal)).unwrap();
+    assert_eq!(engine.try_find(&format!("{}7", literal.to_uppercase())).map(|m| m.is_some()), Ok(true));
+}
+
+#[test]
+fn match_text_at_sees_earlier_text() {
+    let engine = RegexEngine::new(r"\bcat").unwrap();
+    assert_eq!(engine.match_text_at("concat cat", 3).map(|m| m.start()), Some(7));
+    assert_eq!(engine.find(&"concat cat"[3..]).map(|m| m.start()), Some(0));
+    let engine = RegexEngine::new(r"(?<=#)\d+").unwrap();
+    assert_eq!(engine.match_text_at("#12 and #34", 2).map(|m| m.as_str()), Some("34"));
+    assert!(RegexEngine::new(r"\Aa").unwrap().match_text_at("aa", 1).is_none());
+}
+
+#[test]
+fn redundant_groups_are_flattened() {
+    let engine = RegexEngine::new("(?:(?:(?:a)))+b").unwrap();
+    assert_eq!(engine.to_string(), "a+b");
+    assert!(!format!("{:?}", engine).contains("NonCapturingGroup"));
+    // Groups that still do something are kept
+    for pattern in ["x(?:a|b)y", "(?:ab)*", "(a)"] {
+        assert_eq!(RegexEngine::new(pattern).unwrap().to_string(), pattern);
+    }
+    assert!(RegexEngine::new("x(?:a|b)y").unwrap().match_text("xby"));
+    assert!(!RegexEngine::new("(?:(?i))a").unwrap().match_text("A"));
+}
+
+#[test]
+fn word_characters() {
+    let engine = RegexEngine::new(r"\w+").unwrap();
+    assert_eq!(engine.find("-hello_world-").map(|m| m.as_str()), Some("hello_world"));
+    assert_eq!(engine.find("naïve").map(|m| m.as_str()), Some("naïve"));
+    assert!(matches(r"^\w$", "_"));
+    assert!(!matches(r"\W", "a_1"));
+    assert!(!matches(r"a\bb", "a_b"));
+    let ascii = RegexBuilder::new(r"\w+").unicode(false).build().unwrap();
+    assert_eq!(ascii.find("naïve").map(|m| m.as_str()), Some("na"));
+    assert!(RegexBuilder::new(r"[\W]").unicode(false).build().unwrap().match_text("ï"));
+}
+
+#[test]
+fn space_characters() {
+    assert!(matches(r"a\sb", "a\u{00A0}b"));
+    assert!(!matches(r"a\Sb", "a\u{00A0}b"));
+    let ascii = RegexBuilder::new(r"a\sb").unicode(false).build().unwrap();
+    assert!(!ascii.match_text("a\u{00A0}b"));
+    assert!(ascii.match_text("a\tb"));
+    assert!(RegexBuilder::new(r"a\Sb").unicode(false).build().unwrap().match_text("a\u{00A0}b"));
+}
+
+#[test]
+#[allow(deprecated)]
+fn unicode_word_is_an_alias_for_unicode() {
+    let ascii = RegexBuilder::new(r"\w+\s").unicode_word(false).build().unwrap();
+    assert!(!ascii.flags().unicode && !ascii.flags().unicode_word());
+    assert_eq!(ascii.find("naïve\u{00A0}ab ").map(|m| m.as_str()), Some("ab "));
+    assert!(RegexBuilder::new("a").unicode(false).unicode_word(true).build().unwrap().flags().unicode_word());
+}
+
+#[cfg(feature = "unicode")]
+#[test]
+fn unicode_properties() {
+    let engine = RegexEngine::new(r"\p{L}+").unwrap();
+    assert_eq!(engine.find("123 漢字かな 456").map(|m| m.as_str()), Some("漢字かな"));
+    assert_eq!(RegexEngine::new(r"\P{Letter}+").unwrap().find("ab 12cd").map(|m| m.as_str()), Some(" 12"));
+    assert!(matches(r"^\pN\p{Lu}\p{Ll}$", "٣Éa"));
+    assert!(matches(r"^[\p{P}\d]+$", "1,2。3!"));
+    assert!(!matches(r"\p{P}", "$+<"));
+    assert!(matches(r"a\p{Z}b", "a\u{3000}b"));
+    assert_eq!(RegexEngine::new(r"\p{Klingon}").unwrap_err(), Error::UnknownProperty("Klingon".to_string()));
+    assert_eq!(RegexEngine::new(r"\p{L").unwrap_err(), Error::UnknownProperty("L".to_string()));
+    assert_eq!(RegexEngine::new(r"[\P{L}]\pL").unwrap().to_string(), r"[\P{L}]\p{L}");
+}
+
+#[test]
+fn unicode_case_folding() {
+    assert!(matches("(?i)straße", "STRASSE"));
+    assert!(matches("(?i)STRASSE", "straße"));
+    assert!(matches("(?i)^strasse$", "Straße"));
+    assert!(!matches("straße", "STRASSE"));
+    assert!(matches("(?i)ΣΊΣΥΦΟΣ", "σίσυφος"));
+    assert!(matches("(?i)[à-ÿ]", "É"));
+    assert!(matches("(?i)office", "OFFICE"));
+    assert!(matches("(?i)ofﬁce", "OFFICE"));
+    assert!(matches(r"(?i)(é)\1", "éÉ"));
+}
+
+#[cfg(feature = "unicode-scripts")]
+#[test]
+fn script_and_block_properties() {
+    let han = RegexEngine::new(r"\p{Script=Han}+").unwrap();
+    assert_eq!(han.find("日本語のテキスト").map(|m| m.as_str()), Some("日本語"));
+    assert_eq!(RegexEngine::new(r"\p{sc=Katakana}+").unwrap().find("日本語のテキスト").map(|m| m.as_str()), Some("テキスト"));
+    assert!(matches(r"^\p{Script=Greek}+$", "λόγος"));
+    assert!(!matches(r"\p{Script=Greek}", "logos"));
+    assert!(matches(r"^\p{Block=Basic_Latin}+$", "plain ascii"));
+    assert!(matches(r"\P{Block=BasicLatin}", "café"));
+    assert_eq!(RegexEngine::new(r"\p{Script=Elvish}").unwrap_err(), Error::UnknownProperty("Script=Elvish".to_string()));
+    assert_eq!(RegexEngine::new(r"\p{blk=basic latin}").unwrap().to_string(), r"\p{Block=BasicLatin}");
+}
+
+#[test]
+fn dot_stops_at_newline() {
+    let engine = RegexEngine::new(".+").unwrap();
+    assert_eq!(engine.find("hello\nworld").map(|m| m.as_str()), Some("hello"));
+    let engine = RegexBuilder::new(".+").dot_matches_newline(true).build().unwrap();
+    assert_eq!(engine.find("hello\nworld").map(|m| m.as_str()), Some("hello\nworld"));
+    assert!(matches("(?s)o.w", "hello\nworld"));
+}
+
+#[test]
+fn groups_in_alternatives_keep_their_own_index() {
+    let engine = RegexEngine::new(r"((\d)|(\w))").unwrap();
+    let caps = engine.captures("7").unwrap();
+    assert_eq!((caps.get(1), caps.get(2)), (Some("7"), Some("7")));
+    assert!(caps.get(3).is_none());
+    let caps = engine.captures("x").unwrap();
+    assert_eq!((caps.get(2), caps.get(3)), (None, Some("x")));
+    assert!(matches(r"(a)|(b)\2", "bb"));
+    assert_eq!(engine.captures_len(), 3);
+    assert_eq!(RegexEngine::new(r"(?:a)(?P<x>b(c))?").unwrap().captures_len(), 2);
+}
+
+#[test]
+fn start_anchor_before_a_group() {
+    assert!(matches("^(abc)", "abcdef"));
+    assert!(!matches("^(abc)", "xabc"));
+    assert!(matches("^(abc|def)x", "defx"));
+    assert!(!matches("^(abc|def)", "xdef"));
+    assert!(matches("^(a|b)+$", "abba"));
+    assert!(!matches("^(a|b)+$", "abca"));
+    // An anchor inside a group still means the start of the text
+    assert!(!matches("x(^a)", "xa"));
+    assert_eq!(RegexEngine::new("^(a|b)").unwrap().to_string(), "^(a|b)");
+}
+
+#[test]
+fn end_anchor_before_trailing_newline() {
+    assert!(matches("world$", "hello world\n"));
+    assert!(matches("^hello world$", "hello world\n"));
+    assert!(!matches("world$", "world\n\n"));
+    assert!(!matches(r"world\z", "world\n"));
+    assert_eq!(RegexEngine::new(".*$").unwrap().find("abc\n").map(|m| m.as_str()), Some("abc"));
+    assert!(matches("(?m)^a$", "b\na\nc"));
+}
+
+#[test]
+fn multi_digit_backreferences() {
+    let ten_groups = "(a)(b)(c)(d)(e)(f)(g)(h)(i)(j)";
+    assert!(matches(&format!(r"{}\10", ten_groups), "abcdefghijj"));
+    assert!(!matches(&format!(r"{}\10", ten_groups), "abcdefghija0"));
+    assert!(matches(&format!(r"{}\1(?:0)", ten_groups), "abcdefghija0"));
+    assert!(matches(r"(a)\1", "aa"));
+    assert!(matches!(RegexEngine::new(r"(a)\2"), Err(Error::InvalidBackreference(2))));
+    assert!(matches!(RegexEngine::new(r"(a)\10"), Err(Error::InvalidBackreference(10))));
+    let engine = RegexEngine::new(&format!(r"{}\1(?:0)", ten_groups)).unwrap();
+    let reparsed = RegexEngine::new(&engine.to_string()).unwrap();
+    assert!(reparsed.match_text("abcdefghija0"));
+}
+
+#[test]
+fn escaped_metacharacters() {
+    for c in ['.', '*', '+', '?', '^', '$', '|', '(', ')', '{', '}', '[', ']', '-', '\\'] {
+        let pattern = format!("a\\{}b", c);
+        assert!(matches(&pattern, &format!("a{}b", c)), "{} should match literally", pattern);
+        assert!(!matches(&pattern, "axb"), "{} should not act as a metacharacter", pattern);
+        let class = format!("[\\{}]", c);
+        assert!(matches(&class, &c.to_string()), "{} should match literally", class);
+    }
+    assert!(matches(r"\(\d+\)", "call (42)"));
+    assert!(!matches(r"\(\d+\)", "call 42"));
+    assert!(matches(r"^\$\d+\.\d{2}$", "$19.99"));
+    assert!(matches(r"a\{2\}", "a{2}"));
+    assert!(!matches(r"a\{2\}", "aa"));
+    assert!(matches(r"\^\|\*", "^|*"));
+}
+
+#[test]
+fn quoted_literals() {
+    assert!(matches(r"\Q(.*)\E", "call (.*) here"));
+    assert!(!matches(r"\Q(.*)\E", "call (x) here"));
+    assert!(matches(r"^\Qa.b\E+$", "a.bbb"));
+    assert!(matches(r"\Q1+1\Ex", "1+1x"));
+    // Without \E the quote runs to the end, and a \Q inside it is literal
+    assert!(matches(r"\Q[a]\Q", r"[a]\Q"));
+    assert!(matches(r"x\Q", "x"));
+}
+
+#[test]
+fn escaped_text_matches_itself() {
+    let mut samples: Vec<String> = (0u8..128).map(|b| (b as char).to_string()).collect();
+    samples.extend(
+        ["a.b", "(x)*", r"\E\Q", "[^]", "1+1=2?", "$5 {a} |b|", "tab\there", "caf\u{E9} \u{A0}#", "\u{1F600}"]
+            .map(str::to_string),
+    );
+    for text in &samples {
+        let escaped = RegexEngine::escape(text);
+        assert!(RegexEngine::new(&escaped).unwrap().match_text(text), "{:?} escaped as {:?}", text, escaped);
+        let spaced = RegexEngine::new(&format!("(?x)^{}$", escaped)).unwrap();
+        assert!(spaced.match_text(text), "{:?} escaped as {:?} in free-spacing mode", text, escaped);
+    }
+    assert!(!RegexEngine::new(&RegexEngine::escape("a.b")).unwrap().match_text("axb"));
+}
+
+#[test]
+fn inline_comments() {
+    assert!(matches(r"\d(?#a digit)\w", "3a"));
+    assert!(!matches(r"\d(?#a digit)\w", "3 a"));
+    assert!(matches(r"^(?#nested ( is fine)(a)\1$", "aa"));
+    assert!(matches(r"(?x) a (?#spaces inside are kept out) b", "ab"));
+    assert!(matches(r"(a(?#x)|b)c", "bc"));
+    assert_eq!(RegexEngine::new("a(?#unterminated").unwrap_err(), Error::UnmatchedParen);
+}
+
+#[test]
+fn keep_start() {
+    let engine = RegexEngine::new(r"foo\Kbar").unwrap();
+    let m = engine.find("a foobar").unwrap();
+    assert_eq!((m.as_str(), m.start(), m.end()), ("bar", 5, 8));
+    assert!(engine.find("a bar").is_none());
+    // Only the last \K that took part in the match counts
+    let engine = RegexEngine::new(r"a\Kb\Kc|x\Ky").unwrap();
+    assert_eq!(engine.find("abc").unwrap().as_str(), "c");
+    assert_eq!(engine.find("xy").unwrap().as_str(), "y");
+    assert_eq!(RegexEngine::new(r"(a\K)*b").unwrap().find("aaab").unwrap().start(), 3);
+    let prices: Vec<_> = RegexEngine::new(r"\$\K\d+").unwrap().find_iter("$3 and $45").map(|m| m.as_str()).collect();
+    assert_eq!(prices, ["3", "45"]);
+    assert_eq!(RegexEngine::new(r"(\w+)=\K\w+").unwrap().replace("key=value", "x"), "key=x");
+}
+
+#[test]
+fn continuation_anchor() {
+    let engine = RegexEngine::new(r"\G\w+").unwrap();
+    assert_eq!(engine.find_iter("foo bar").map(|m| m.as_str()).collect::<Vec<_>>(), ["foo"]);
+    assert!(engine.find(" foo").is_none());
+    let engine = RegexEngine::new(r"\G(\d),?").unwrap();
+    let digits: Vec<_> = engine.captures_iter("1,2,3 4").map(|caps| caps.get(1).unwrap().to_string()).collect();
+    assert_eq!(digits, ["1", "2", "3"]);
+    // Searching from an offset starts \G there
+    assert_eq!(RegexEngine::new(r"\Gb").unwrap().match_text_at("abc", 1).unwrap().start(), 1);
+}
+
+#[test]
+fn conditionals() {
+    let engine = RegexEngine::new(r"^(\d)?(?(1)\d|[a-z])$").unwrap();
+    assert!(engine.match_text("12"));
+    assert!(engine.match_text("x"));
+    assert!(!engine.match_text("1x"));
+    assert!(!engine.match_text("5"));
+    // Without a no branch the conditional matches nothing when the group did not take part
+    let quoted = RegexEngine::new(r"^(<)?\w+(?(1)>)$").unwrap();
+    assert!(quoted.match_text("<a>") && quoted.match_text("a"));
+    assert!(!quoted.match_text("<a") && !quoted.match_text("a>"));
+    assert_eq!(RegexEngine::new(r"(a)(?(2)b|c)").unwrap_err(), Error::InvalidConditionGroup(2));
+    assert_eq!(RegexEngine::new(r"(a)(?(1)b|c|d)").unwrap_err(), Error::TooManyConditionalBranches);
+    assert_eq!(quoted.to_string(), r"^(<)?\w+(?(1)>)$");
+}
+
+#[test]
+fn match_all() {
+    let spans = |matches: Vec<mygrep::Match>| matches.iter().map(|m| (m.start(), m.end())).collect::<Vec<_>>();
+    let engine = RegexEngine::new("a*").unwrap();
+    assert_eq!(spans(engine.match_all("bbb")), [(0, 0), (1, 1), (2, 2), (3, 3)]);
+    assert_eq!(spans(engine.match_all("baab")), [(0, 0), (1, 3), (3, 3), (4, 4)]);
+    assert_eq!(spans(RegexEngine::new("é?").unwrap().match_all("ü")), [(0, 0), (2, 2)]);
+
+    let engine = RegexEngine::new("aa").unwrap();
+    assert_eq!(spans(engine.match_all("aaa")), [(0, 2)]);
+    assert_eq!(spans(engine.match_all_overlapping("aaa")), [(0, 2), (1, 3)]);
+    assert_eq!(spans(RegexEngine::new(r"\d+").unwrap().match_all_overlapping("a12")), [(1, 3), (2, 3)]);
+    assert_eq!(spans(RegexEngine::new("x*").unwrap().match_all_overlapping("ab")), [(0, 0), (1, 1), (2, 2)]);
+}
+
+#[test]
+fn full_matches() {
+    let engine = RegexEngine::new("f.o").unwrap();
+    assert!(engine.is_full_match("foo"));
+    assert!(!engine.is_full_match("foox"));
+    assert!(!engine.is_full_match("foo\n"));
+    assert!(!RegexEngine::new("fo").unwrap().is_full_match("foo"));
+    // Every alternative is tried, not just the first one that matches a prefix
+    assert_eq!(RegexEngine::new("a|ab").unwrap().full_match("ab").unwrap().as_str(), "ab");
+    assert!(!RegexBuilder::new("b$").multiline(true).build().unwrap().is_full_match("b\nc"));
+    assert!(RegexEngine::new("").unwrap().is_full_match(""));
+}
+
+#[test]
+fn starts_and_ends_with() {
+    let engine = RegexEngine::new("abc").unwrap();
+    let span = |m: Option<mygrep::Match>| m.map(|m| (m.start(), m.end()));
+    assert_eq!(span(engine.find("abcabc")), Some((0, 3)));
+    assert_eq!(span(engine.starts_with("abcabc")), Some((0, 3)));
+    assert_eq!(span(engine.ends_with("abcabc")), Some((3, 6)));
+    assert_eq!(span(engine.starts_with("xabc")), None);
+    assert_eq!(span(engine.ends_with("abcx")), None);
+    assert_eq!(span(engine.ends_with("abc\n")), None);
+    // The leftmost match that reaches the end is the one reported
+    assert_eq!(span(RegexEngine::new(r"\d+").unwrap().ends_with("a1234")), Some((1, 5)));
+    assert_eq!(span(RegexBuilder::new("^b").multiline(true).build().unwrap().starts_with("a\nb")), None);
+}
+
+#[test]
+fn grep_lines_streams_selected_lines() {
+    let engine = RegexEngine::new(r"\d+").unwrap();
+    let input = "a1b22\r\nnone\n333\n";
+    let selected: Vec<_> = grep_lines(&engine, input.as_bytes(), &GrepOptions::default()).map(Result::unwrap).collect();
+    assert_eq!(selected.len(), 2);
+    assert_eq!((selected[0].line_number, selected[0].line.as_str()), (1, "a1b22"));
+    assert_eq!(selected[0].matches, [1..2, 3..5]);
+    assert_eq!((selected[1].line_number, selected[1].line.as_str()), (3, "333"));
+
+    let invert = GrepOptions { invert: true };
+    let selected: Vec<_> = grep_lines(&engine, input.as_bytes(), &invert).map(Result::unwrap).collect();
+    assert_eq!(selected.len(), 1);
+    assert_eq!((selected[0].line_number, selected[0].line.as_str()), (2, "none"));
+    assert!(selected[0].matches.is_empty());
+
+    // A line that is not UTF-8 is matched with the bad bytes replaced, and keeps them in `bytes`
+    let selected: Vec<_> = grep_lines(&engine, &b"\xFF1\n2\r\n"[..], &GrepOptions::default()).map(Result::unwrap).collect();
+    assert_eq!((selected[0].line.as_str(), selected[0].bytes.as_slice()), ("\u{FFFD}1", &b"\xFF1"[..]));
+    assert_eq!(selected[0].matches, vec![3..4]);
+    assert_eq!((selected[1].line_number, selected[1].bytes.as_slice()), (2, &b"2"[..]));
+
+    // A search that runs past a limit is reported without ending the search
+    let engine = RegexBuilder::new("(a+)+b").backtrack_limit(100).build().unwrap();
+    let results: Vec<_> = grep_lines(&engine, &b"aaaaaaaaaaaaaaaaaaaa\nab\n"[..], &GrepOptions::default()).collect();
+    assert_eq!(results.len(), 2);
+    assert!(results[0].is_err());
+    assert_eq!(results[1].as_ref().unwrap().line_number, 2);
+}
+
+#[test]
+fn reused_match_cache() {
+    let mut cache = MatchCache::new();
+    let digits = RegexEngine::new(r"(\d+)+x").unwrap();
+    let word = RegexEngine::new(r"^\w+$").unwrap();
+    for text in ["12x", "123", "a12x", "", "9999999999"] {
+        assert_eq!(digits.match_text_reuse(text, &mut cache), digits.match_text(text), "{:?}", text);
+        // The same cache serves another engine without carrying anything over
+        assert_eq!(word.match_text_reuse(text, &mut cache), word.match_text(text), "{:?}", text);
+    }
+}
+
+#[test]
+fn engines_are_shared_between_threads() {
+    fn assert_send_sync<T: Send + Sync + Clone>() {}
+    assert_send_sync::<RegexEngine>();
+    assert_send_sync::<RegexSet>();
+
+    let engine = std::sync::Arc::new(RegexEngine::new(r"(\d+)-(\d+)").unwrap());
+    let handles: Vec<_> = (0..4)
+        .map(|i| {
+            let engine = engine.clone();
+            std::thread::spawn(move || engine.captures(&format!("{}-{}", i, i * 2)).unwrap().get(2).unwrap().to_string())
+        })
+        .collect();
+    let results: Vec<_> = handles.into_iter().map(|handle| handle.join().unwrap()).collect();
+    assert_eq!(results, ["0", "2", "4", "6"]);
+    let copy = engine.as_ref().clone();
+    assert_eq!(copy.as_str(), engine.as_str());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_round_trip() {
+    let patterns = [r"^(\w+)@(?P<host>[a-z.]+)$", r"(?i)caf[é\d]{2,}?\b", r"(a|b)\1(?(1)x|y)", r"\p{Lu}+"];
+    let texts = ["me@example.com", "CAFé12 ", "aax", "ABC", "nothing"];
+    for pattern in patterns {
+        let engine = RegexEngine::new(pattern).unwrap();
+        let json = serde_json::to_string(&engine).unwrap();
+        let loaded: RegexEngine = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.as_str(), pattern);
+        for text in texts {
+            assert_eq!(loaded.find(text), engine.find(text), "{} on {:?}", pattern, text);
+        }
+    }
+}
+
+#[test]
+fn dot_graph() {
+    let dot = RegexEngine::new(r#"(a|")+\d"#).unwrap().to_dot();
+    assert!(dot.starts_with("digraph pattern {\n"));
+    assert!(dot.ends_with("}\n"));
+    for line in [
+        r#"n0 [label="Pattern"];"#,
+        r#"n1 [label="Repeat +"];"#,
+        r#"n2 [label="Group 1"];"#,
+        r#"n3 [label="Alternation"];"#,
+        r#"n7 [label="Char\n\""];"#,
+        r#"n8 [label="Digit\n\\d"];"#,
+        r#"n0 -> n8 [label="2"];"#,
+    ] {
+        assert!(dot.contains(line), "{} missing from\n{}", line, dot);
+    }
+}
+
+#[test]
+fn byte_slices() {
+    let engine = RegexEngine::new("b+").unwrap();
+    assert!(engine.match_bytes(b"abbc"));
+    let m = engine.find_bytes(b"abbc").unwrap();
+    assert_eq!((m.start(), m.end(), m.as_bytes()), (1, 3, &b"bb"[..]));
+    // Invalid UTF-8 matches nothing unless the engine is in binary mode
+    assert!(!engine.match_bytes(b"b\xff"));
+    assert!(engine.find_iter_bytes(b"b\xffb").next().is_none());
+
+    let binary = RegexBuilder::new("\\xFF.b+").binary_mode(true).build().unwrap();
+    let input = b"\x00\xff\x00bb\xfe\xff\x80b";
+    let found: Vec<_> = binary.find_iter_bytes(input).map(|m| (m.start(), m.end())).collect();
+    assert_eq!(found, [(1, 5), (6, 9)]);
+    assert!(!binary.match_bytes(b"\xff\nb"));
+    assert!(RegexBuilder::new("^[\\x80-\\xFF]+$").binary_mode(true).build().unwrap().match_bytes(b"\xc3\x80\xa0"));
+}
+
+#[test]
+fn failed_attempts_leave_no_captures() {
+    // Group 1 is only set in an alternative that was not taken
+    assert!(!matches(r"(a)|b\1", "b"));
+    assert!(!matches(r"^(?:(a)x|a)\1$", "aa"));
+    // The group matched "a" before its alternative failed at 'b', so it must not be kept
+    let caps = RegexEngine::new(r"(?:(a)b|ac)").unwrap().captures("ac").unwrap();
+    assert_eq!((caps.get(0), caps.get(1)), (Some("ac"), None));
+    // Nor does an attempt that failed at an earlier start leave its captures to a later match
+    let caps = RegexEngine::new(r"(x)?(a)c").unwrap().captures("xab ac").unwrap();
+    assert_eq!((caps.get(0), caps.get(1), caps.get(2)), (Some("ac"), None, Some("a")));
+}
+
+#[test]
+fn character_escapes() {
+    assert!(matches(r"^\t$", "\t"));
+    assert!(matches(r"^\n$", "\n"));
+    assert!(matches(r"^\r$", "\r"));
+    assert!(matches(r"^\a$", "\x07"));
+    assert!(matches(r"^\f$", "\x0C"));
+    assert!(matches(r"^\e$", "\x1B"));
+    assert!(matches(r"^a\tb\r\nc$", "a\tb\r\nc"));
+    assert!(!matches(r"a\tb", "a b"));
+
+    assert!(matches(r"^\x41$", "A"));
+    assert!(matches(r"^\x6a\x6A$", "jj"));
+    assert!(matches(r"^\x{263A}$", "☺"));
+    assert!(matches(r"^\x{41}\x{1F600}$", "A😀"));
+    assert!(matches(r"^[\x{263A}\x41-\x43]+$", "B☺A"));
+    assert!(!matches(r"\x41", "a"));
+    assert_eq!(RegexEngine::new(r"\x4").unwrap_err(), Error::InvalidHexEscape('x', 2));
+    assert_eq!(RegexEngine::new(r"\x{}").unwrap_err(), Error::InvalidHexEscape('x', 2));
+    assert_eq!(RegexEngine::new(r"\x{263A").unwrap_err(), Error::InvalidHexEscape('x', 2));
+    assert_eq!(RegexEngine::new(r"\x{D800}").unwrap_err(), Error::InvalidCodePoint('x', 0xD800));
+    assert_eq!(RegexEngine::new(r"\x{263A}").unwrap().to_string(), "☺");
+}
+
+#[test]
+fn lazy_and_possessive_quantifiers() {
+    assert_eq!(found("a+", "aaa"), Some("aaa"));
+    assert_eq!(found("a+?", "aaa"), Some("a"));
+    assert_eq!(found("a*?", "aaa"), Some(""));
+    assert_eq!(found("a??b", "ab"), Some("ab"));
+    assert_eq!(found("a{2,}?", "aaaa"), Some("aa"));
+    assert_eq!(found("<.+?>", "<a><b>"), Some("<a>"));
+    assert_eq!(found("<.+>", "<a><b>"), Some("<a><b>"));
+    // A lazy quantifier still takes more when the rest of the pattern needs it
+    assert_eq!(found("a+?b", "aaab"), Some("aaab"));
+    assert_eq!(found("^a*?$", "aaa"), Some("aaa"));
+
+    assert_eq!(found("a++", "aaa"), Some("aaa"));
+    assert_eq!(found("a*+b", "aab"), Some("aab"));
+    assert_eq!(found("a{1,2}+", "aaa"), Some("aa"));
+    // A possessive quantifier never gives back what it took
+    assert!(!matches("a++a", "aaa"));
+    assert!(!matches("^.*+b$", "ab"));
+    assert!(!matches("a?+a", "a"));
+    assert!(matches("a+a", "aaa"));
+}
+
+#[test]
+fn text_anchors() {
+    assert!(matches(r"\Afoo", "foo\n"));
+    assert!(!matches(r"\Afoo", "bar\nfoo"));
+    assert!(matches(r"foo\z", "bar\nfoo"));
+    assert!(!matches(r"foo\z", "foo\n"));
+    assert!(matches(r"foo\Z", "foo"));
+    assert!(matches(r"foo\Z", "foo\n"));
+    assert!(!matches(r"foo\Z", "foo\n\n"));
+    assert_eq!(found(r"\Z", "ab\n").map(str::len), Some(0));
+    assert_eq!(RegexEngine::new(r"\Z").unwrap().find("ab\n").map(|m| m.start()), Some(2));
+    // Unlike ^ and $, they ignore multiline mode
+    let multiline = |pattern| RegexBuilder::new(pattern).multiline(true).build().unwrap();
+    assert!(multiline("^foo$").match_text("bar\nfoo\nbaz"));
+    assert!(!multiline(r"\Afoo").match_text("bar\nfoo"));
+    assert!(!multiline(r"foo\z").match_text("foo\nbar"));
+    assert!(!multiline(r"foo\Z").match_text("foo\nbar"));
+    assert!(multiline(r"\Abar\nfoo\Z").match_text("bar\nfoo\n"));
+}
+
+#[test]
+fn unicode_escapes() {
+    assert!(matches(r"^\u00E9$", "é"));
+    assert!(matches(r"^\u00e9\u00C9$", "éÉ"));
+    assert!(matches(r"^\U0001F600$", "😀"));
+    assert!(matches(r"^[\u0430-\u044F]+$", "привет"));
+    assert!(!matches(r"\u00E9", "e"));
+    assert_eq!(RegexEngine::new(r"\u00E").unwrap_err(), Error::InvalidHexEscape('u', 4));
+    assert_eq!(RegexEngine::new(r"\uD800").unwrap_err(), Error::InvalidCodePoint('u', 0xD800));
+    assert_eq!(RegexEngine::new(r"\U00110000").unwrap_err(), Error::InvalidCodePoint('U', 0x110000));
+    assert_eq!(RegexEngine::new(r"\u00E9\U0001F600").unwrap().to_string(), "é😀");
+}
+
+#[test]
+fn word_boundaries() {
+    assert!(matches(r"\bcat\b", "the cat sat"));
+    assert!(matches(r"\bcat\b", "cat"));
+    assert!(matches(r"\bcat\b", "(cat)"));
+    assert!(!matches(r"\bcat\b", "concatenate"));
+    assert!(!matches(r"\bcat\b", "cats"));
+    assert_eq!(found(r"\b\w+\b", "  hello world"), Some("hello"));
+    // The start and end of the text count as non-word characters
+    assert!(matches(r"^\b", "a"));
+    assert!(matches(r"\b$", "a"));
+    assert!(!matches(r"\b", ""));
+    assert!(!matches(r"\b", "  "));
+
+    assert!(matches(r"\Bcat", "concat"));
+    assert!(!matches(r"\Bcat", "the cat"));
+    assert!(matches(r"cat\B", "cats"));
+    assert!(!matches(r"cat\B", "cat."));
+    assert!(matches(r"^\B$", ""));
+    assert!(matches(r"\B", "  "));
+    assert_eq!(found(r"\B\w+", "hello"), Some("ello"));
+}
+
+#[test]
+fn atomic_groups() {
+    assert!(!matches("^(?>a+)a$", "aa"));
+    assert!(!matches("(?>a+)a", "aa"));
+    assert!(matches("^(?:a+)a$", "aa"));
+    assert_eq!(found("(?>a+)b", "aaab"), Some("aaab"));
+    // The group commits to its first alternative that matches
+    assert!(!matches("^(?>ab|a)b$", "ab"));
+    assert!(matches("^(?:ab|a)b$", "ab"));
+    let caps = RegexEngine::new("(?>(a+))b").unwrap().captures("xaab").unwrap();
+    assert_eq!((caps.get(0), caps.get(1)), (Some("aab"), Some("aa")));
+}
+
+#[test]
+fn lookarounds() {
+    assert_eq!(found(r"\w+(?=\s)", "hello world"), Some("hello"));
+    assert!(!matches(r"\w+(?=\s)", "hello"));
+    assert_eq!(found(r"(?:\d(?=\d))+", "a12345"), Some("1234"));
+    let caps = RegexEngine::new(r"(\w+)(?=(\d))").unwrap().captures("ab1").unwrap();
+    assert_eq!((caps.get(0), caps.get(1), caps.get(2)), (Some("ab"), Some("ab"), Some("1")));
+    assert_eq!(found(r"\b(?!foo)\w+", "foobar baz"), Some("baz"));
+
+    assert_eq!(found("(?<=foo)bar", "foobar"), Some("bar"));
+    assert!(!matches("(?<=foo)bar", "fobar"));
+    assert!(!matches("(?<=foo)bar", "bar"));
+    assert_eq!(RegexEngine::new("(?<=foo)bar").unwrap().find("xfoobar").map(|m| m.start()), Some(4));
+    assert!(matches("(?<!foo)bar", "bar"));
+    assert!(matches("(?<!foo)bar", "fobar"));
+    assert!(!matches("(?<!foo)bar", "foobar"));
+    assert_eq!(found("(?<!foo)bar", "foobar xbar"), Some("bar"));
+    assert_eq!(RegexEngine::new("(?<!foo)bar").unwrap().find("foobar xbar").map(|m| m.start()), Some(8));
+    // Captures inside a negative lookaround are never kept
+    let caps = RegexEngine::new("(?<!(x))b").unwrap().captures("ab").unwrap();
+    assert_eq!(caps.get(1), None);
+}
+
+#[test]
+fn posix_classes() {
+    let cases = [
+        ("alpha", "x", "1"),
+        ("digit", "7", "x"),
+        ("alnum", "7", "-"),
+        ("upper", "X", "x"),
+        ("lower", "x", "X"),
+        ("space", "\t", "x"),
+        ("blank", " ", "\n"),
+        ("punct", "!", "x"),
+        ("print", " ", "\x07"),
+        ("graph", "x", " "),
+        ("cntrl", "\x07", "x"),
+        ("xdigit", "f", "g"),
+    ];
+    for (name, inside, outside) in cases {
+        let positive = format!("^[[:{}:]]$", name);
+        let negated = format!("^[^[:{}:]]$", name);
+        assert!(matches(&positive, inside), "{} should match {:?}", positive, inside);
+        assert!(!matches(&positive, outside), "{} should not match {:?}", positive, outside);
+        assert!(!matches(&negated, inside), "{} should not match {:?}", negated, inside);
+        assert!(matches(&negated, outside), "{} should match {:?}", negated, outside);
+    }
+    assert_eq!(found("[^[:digit:]]+", "123abc456"), Some("abc"));
+    assert_eq!(found("[[:digit:][:upper:]_]+", "x1A_2b"), Some("1A_2"));
+    assert_eq!(RegexEngine::new("[[:nope:]]").unwrap_err(), Error::UnknownPosixClass("nope".to_string()));
+}
+
+#[test]
+fn multiline_anchors() {
+    assert!(!matches("^foo", "bar\nfoo"));
+    assert!(matches("(?m)^foo", "bar\nfoo"));
+    assert_eq!(RegexEngine::new("(?m)^foo").unwrap().find("bar\nfoo").map(|m| m.start()), Some(4));
+    assert!(!matches("bar$", "bar\nfoo"));
+    assert!(matches("(?m)bar$", "bar\nfoo"));
+    assert!(!matches("(?m)^oo", "bar\nfoo"));
+    let lines: Vec<_> = RegexEngine::new(r"(?m)^\w+$").unwrap().find_iter("one\ntwo\n\nthree").map(|m| m.as_str()).collect();
+    assert_eq!(lines, ["one", "two", "three"]);
+}
+
+#[test]
+fn scoped_inline_flags() {
+    assert!(matches("^(?i:foo)bar$", "FOObar"));
+    assert!(!matches("^(?i:foo)bar$", "FOOBAR"));
+    assert!(matches("^(?i)foo(?-i)bar$", "FoObar"));
+    assert!(!matches("^(?i)foo(?-i)bar$", "FoOBAR"));
+    assert!(matches("^(?i)a(?-i:b)c$", "AbC"));
+    assert!(!matches("^(?i)a(?-i:b)c$", "ABC"));
+    // A flag set inside a group ends with the group
+    assert!(matches("^(?:(?i)a)a$", "Aa"));
+    assert!(!matches("^(?:(?i)a)a$", "AA"));
+
+    assert!(matches(r"(?x) \d{4} - \d{2} # year-month", "2024-06"));
+    assert!(!matches(r"(?x) \d{4} - \d{2} # year-month", "2024 - 06"));
+    assert!(matches(r"(?x) a\ b [ ] c", "a b c"));
+    assert!(matches(r"^(?x: a b )c d$", "abc d"));
+    assert!(matches("(?ix) A B", "ab"));
+}
+
+#[test]
+fn alternation_with_many_arms() {
+    let engine = RegexEngine::new("(cat|dog|bird)").unwrap();
+    for animal in ["cat", "dog", "bird"] {
+        let text = format!("a {} here", animal);
+        let caps = engine.captures(&text).unwrap();
+        assert_eq!((caps.get(0), caps.get(1)), (Some(animal), Some(animal)));
+    }
+    assert!(!engine.match_text("cow"));
+    let caps = RegexEngine::new("^(cat|dog|bird)s? and (cat|dog|bird)s?$").unwrap().captures("birds and dog").unwrap();
+    assert_eq!((caps.get(1), caps.get(2)), (Some("bird"), Some("dog")));
+    // Arms are tried in order, so the first one that matches wins
+    assert_eq!(found("(a|ab|abc)", "abc"), Some("a"));
+    assert_eq!(found("(a|ab|abc)$", "abc"), Some("abc"));
+    assert_eq!(found("(|x|y)", "y"), Some(""));
+
+    assert!(matches("^foo|bar$", "foo!"));
+    assert!(matches("^foo|bar$", "!bar"));
+    assert!(!matches("^foo|bar$", "!foo bar!"));
+}
+
+#[test]
+fn non_ascii_text() {
+    assert_eq!(found("世界", "你好世界"), Some("世界"));
+    assert_eq!(found("^你.世", "你好世界"), Some("你好世"));
+    assert_eq!(found("[世界]+", "你好世界!"), Some("世界"));
+    assert_eq!(found("[^你好]+", "你好世界"), Some("世界"));
+    assert_eq!(found("مرحبا", "قال مرحبا"), Some("مرحبا"));
+    assert_eq!(found(r"\w+$", "قال مرحبا"), Some("مرحبا"));
+    assert_eq!(found("[ا-ي]+", "abc سلام"), Some("سلام"));
+    assert_eq!(found("😀+", "hi 😀😀!"), Some("😀😀"));
+    assert_eq!(found("[😀-😂]", "a😁b"), Some("😁"));
+    assert_eq!(found("a.b", "a🎉b"), Some("a🎉b"));
+    assert_eq!(found("^.{3}$", "世😀م"), Some("世😀م"));
+    assert!(!matches("^.$", "世界"));
+    let m = RegexEngine::new("界").unwrap().find("你好世界").unwrap();
+    assert_eq!((m.start(), m.end()), (9, 12));
+    let words: Vec<_> = RegexEngine::new("[^ ]+").unwrap().find_iter("你好 😀 مرحبا").map(|m| m.as_str()).collect();
+    assert_eq!(words, ["你好", "😀", "مرحبا"]);
+}
+
+#[test]
+fn repeated_alternation_over_long_input() {
+    // Each repetition recurses, so these need more than a test thread's stack, and the longest
+    // run more than the default depth limit
+    let search = std::thread::Builder::new().stack_size(1 << 30).spawn(|| {
+        let text = "ab".repeat(50);
+        let caps = RegexEngine::new("^(a|b)+$").unwrap().captures(&text).unwrap();
+        assert_eq!((caps.get(0), caps.get(1)), (Some(text.as_str()), Some("b")));
+        assert_eq!(found("(a|b)+", &format!("xx{}c", text)), Some(text.as_str()));
+        assert!(matches("^(a|b)+c$", &format!("{}c", text)));
+        assert!(!matches("^(a|b)+$", &format!("{}c", text)));
+
+        let text = "ab".repeat(5_000);
+        let engine = RegexBuilder::new("^(a|b)+$").depth_limit(100_000).build().unwrap();
+        assert_eq!(engine.try_find(&text).map(|m| m.map(|m| m.end())), Ok(Some(10_000)));
+    });
+    search.unwrap().join().unwrap();
+}
+
+#[test]
+fn quantified_alternation() {
+    assert!(matches("^(a|b)+$", "ababab"));
+    assert_eq!(found("(a|b)+", "xxababab!"), Some("ababab"));
+    assert_eq!(found("(cat|dog)+", "a catdogcat!"), Some("catdogcat"));
+    assert_eq!(found("(a|b)*", "ababc"), Some("abab"));
+    assert_eq!(found("(a|b)+?", "abab"), Some("a"));
+    assert_eq!(found("(ab|c){2}", "abababcab"), Some("abab"));
+    assert_eq!(found("(?:ab|c){2,3}", "cabcab"), Some("cabc"));
+    assert!(!matches("^(a|b)+$", "abcab"));
+    assert!(!matches("(cat|dog)+", "cow"));
+    let caps = RegexEngine::new("(cat|dog)+").unwrap().captures("catdog").unwrap();
+    assert_eq!(caps.get(1), Some("dog"));
+}